@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+/// Alt-Svc 头里没有 `ma=` 参数时使用的保守默认有效期
+pub const DEFAULT_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// 单个 host:port 的 HTTP/3 探测结果，`expires_at` 是 Unix 秒时间戳，
+/// 来自 Alt-Svc 头里的 `ma=` (max-age)，过期后需要重新探测
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct AltSvcEntry {
+    supports_h3: bool,
+    expires_at: u64,
+}
+
+fn cache_path() -> std::path::PathBuf {
+    std::env::temp_dir().join("tthsdnext_alt_svc_cache.json")
+}
+
+fn load_from_disk() -> HashMap<String, AltSvcEntry> {
+    std::fs::read(cache_path())
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_to_disk(map: &HashMap<String, AltSvcEntry>) {
+    if let Ok(data) = serde_json::to_vec(map) {
+        let _ = std::fs::write(cache_path(), data);
+    }
+}
+
+/// 进程级 Alt-Svc 缓存；首次访问时从磁盘加载，此后所有下载任务共享同一份内存副本，
+/// 每次 `record` 再整体落盘一次，让进程重启后第一次请求也能跳过探测
+fn cache() -> &'static Mutex<HashMap<String, AltSvcEntry>> {
+    static CACHE: once_cell::sync::Lazy<Mutex<HashMap<String, AltSvcEntry>>> =
+        once_cell::sync::Lazy::new(|| Mutex::new(load_from_disk()));
+    &CACHE
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// 从 URL 提取 `host:port` 作为缓存 key，没有显式端口时按 scheme 补上默认端口，
+/// 这样 `https://a.com` 和 `https://a.com:443` 能命中同一条缓存
+fn cache_key(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    let port = parsed.port_or_known_default()?;
+    Some(format!("{}:{}", host, port))
+}
+
+/// 查询已知的 HTTP/3 支持情况: `Some(true/false)` 表示有仍然有效的缓存结果（正反都算数，
+/// 省去重复探测已知不支持 h3 的主机），`None` 表示从未探测过或已过期
+pub fn lookup(url: &str) -> Option<bool> {
+    let key = cache_key(url)?;
+    let guard = cache().lock().unwrap();
+    let entry = guard.get(&key)?;
+    if entry.expires_at <= now() {
+        return None;
+    }
+    Some(entry.supports_h3)
+}
+
+/// 记录一次探测结果，`max_age_secs` 通常来自 Alt-Svc 的 `ma=` 参数
+pub fn record(url: &str, supports_h3: bool, max_age_secs: u64) {
+    let Some(key) = cache_key(url) else { return };
+    let entry = AltSvcEntry { supports_h3, expires_at: now() + max_age_secs };
+
+    let mut guard = cache().lock().unwrap();
+    guard.insert(key, entry);
+    save_to_disk(&guard);
+}
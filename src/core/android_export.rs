@@ -6,7 +6,7 @@ use jni::JNIEnv;
 #[cfg(feature = "android")]
 use jni::objects::{JClass, JString, JObject, JValue};
 #[cfg(feature = "android")]
-use jni::sys::{jint, jboolean};
+use jni::sys::{jint, jboolean, jstring};
 #[cfg(feature = "android")]
 use std::sync::{Arc, Mutex};
 #[cfg(feature = "android")]
@@ -17,7 +17,7 @@ use std::ffi::CString;
 use tokio::sync::RwLock;
 
 #[cfg(feature = "android")]
-use super::downloader::{HSDownloader, DownloadTask, DownloadConfig, Event, EventType, UA};
+use super::downloader::{HSDownloader, DownloadTask, DownloadConfig, Event, EventType, UA, DEFAULT_MAX_RETRIES, DEFAULT_MAX_CONCURRENT_TASKS};
 #[cfg(feature = "android")]
 use super::send_message::send_message;
 
@@ -43,6 +43,38 @@ fn get_downloader_id() -> &'static Mutex<i32> {
     &DOWNLOADER_ID
 }
 
+/// 如果下载器配置了 `db_path`，把它当前的配置写入持久化目录
+///
+/// 只在暂停/恢复/创建等状态转换时调用，不会跟着每个字节的下载进度触发，
+/// 借此把落盘频率限制在一个可接受的范围内
+#[cfg(feature = "android")]
+async fn persist_if_configured(downloader: &Arc<RwLock<HSDownloader>>, id: i32) {
+    let config = downloader.read().await.config.clone();
+    let cfg = config.read().await;
+    if let Some(ref db_path) = cfg.db_path {
+        let entry = super::persistence::PersistedDownloader {
+            id,
+            config: super::persistence::PersistedConfig::from(&*cfg),
+            chunk_bitmap: Vec::new(),
+        };
+        if let Err(e) = super::persistence::save(db_path, &entry) {
+            eprintln!("持久化下载器 {} 失败: {:?}", id, e);
+        }
+    }
+}
+
+/// 下载器完成/停止后，如果配置了 `db_path`，从持久化目录移除它的记录
+#[cfg(feature = "android")]
+async fn prune_if_configured(downloader: &Arc<RwLock<HSDownloader>>, id: i32) {
+    let config = downloader.read().await.config.clone();
+    let cfg = config.read().await;
+    if let Some(ref db_path) = cfg.db_path {
+        if let Err(e) = super::persistence::prune(db_path, id) {
+            eprintln!("清理持久化记录 {} 失败: {:?}", id, e);
+        }
+    }
+}
+
 /// JNI 函数: 启动下载任务
 /// 
 /// 参数说明:
@@ -69,6 +101,8 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_startDownload<'local>(
     callback_url: JString,
     use_socket: jboolean,
     is_multiple: jboolean,
+    auth_token: JString,
+    db_path: JString,
 ) -> jint {
     // 转换 JSON 字符串
     let tasks_str: String = match env.get_string(&tasks_json) {
@@ -98,6 +132,24 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_startDownload<'local>(
         None
     };
 
+    // 获取回调 WebSocket 鉴权令牌，空字符串等同未设置
+    let token = match env.get_string(&auth_token) {
+        Ok(s) => {
+            let s = String::from(s);
+            if !s.is_empty() { Some(s) } else { None }
+        }
+        Err(_) => None,
+    };
+
+    // 持久化目录，应用私有存储下的路径，空字符串等同未设置（不持久化）
+    let persist_path = match env.get_string(&db_path) {
+        Ok(s) => {
+            let s = String::from(s);
+            if !s.is_empty() { Some(s) } else { None }
+        }
+        Err(_) => None,
+    };
+
     let config = DownloadConfig {
         tasks,
         thread_count: thread_count as usize,
@@ -108,6 +160,19 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_startDownload<'local>(
         use_socket: if use_socket != jni::sys::JNI_FALSE { Some(true) } else { None },
         show_name: String::new(),
         user_agent: UA.to_string(),
+        extract: None,
+        db_path: persist_path,
+        max_speed_bps: None,
+        max_retries: DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: token,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
     };
 
     let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
@@ -125,6 +190,8 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_startDownload<'local>(
 
     let downloader_clone = downloader.clone();
     RUNTIME.spawn(async move {
+        persist_if_configured(&downloader_clone, downloader_id).await;
+
         let result = if is_multiple != jni::sys::JNI_FALSE {
             downloader_clone.read().await.start_multiple_downloads().await
         } else {
@@ -149,6 +216,8 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_startDownload<'local>(
             let _ = send_message(event, data, &config, &ws_client, &socket_client).await;
         }
 
+        prune_if_configured(&downloader_clone, downloader_id).await;
+
         let mut downloaders = get_downloaders().lock().unwrap();
         downloaders.remove(&downloader_id);
     });
@@ -168,6 +237,8 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getDownloader<'local>(
     use_callback_url: jboolean,
     callback_url: JString,
     use_socket: jboolean,
+    auth_token: JString,
+    db_path: JString,
 ) -> jint {
     let tasks_str: String = match env.get_string(&tasks_json) {
         Ok(s) => String::from(s),
@@ -191,6 +262,22 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getDownloader<'local>(
         None
     };
 
+    let token = match env.get_string(&auth_token) {
+        Ok(s) => {
+            let s = String::from(s);
+            if !s.is_empty() { Some(s) } else { None }
+        }
+        Err(_) => None,
+    };
+
+    let persist_path = match env.get_string(&db_path) {
+        Ok(s) => {
+            let s = String::from(s);
+            if !s.is_empty() { Some(s) } else { None }
+        }
+        Err(_) => None,
+    };
+
     let config = DownloadConfig {
         tasks,
         thread_count: thread_count as usize,
@@ -201,6 +288,19 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getDownloader<'local>(
         use_socket: if use_socket != jni::sys::JNI_FALSE { Some(true) } else { None },
         show_name: String::new(),
         user_agent: UA.to_string(),
+        extract: None,
+        db_path: persist_path,
+        max_speed_bps: None,
+        max_retries: DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: token,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
     };
 
     let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
@@ -213,9 +313,11 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getDownloader<'local>(
 
     {
         let mut downloaders = get_downloaders().lock().unwrap();
-        downloaders.insert(downloader_id, downloader);
+        downloaders.insert(downloader_id, downloader.clone());
     }
 
+    RUNTIME.block_on(persist_if_configured(&downloader, downloader_id));
+
     downloader_id
 }
 
@@ -325,6 +427,7 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_pauseDownload<'local>(
         Some(d) => {
             RUNTIME.block_on(async {
                 d.read().await.pause_download().await;
+                persist_if_configured(&d, id).await;
             });
             0
         }
@@ -347,7 +450,9 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_resumeDownload<'local>(
     match downloader {
         Some(d) => {
             let result = RUNTIME.block_on(async {
-                d.read().await.resume_download().await
+                let result = d.read().await.resume_download().await;
+                persist_if_configured(&d, id).await;
+                result
             });
             match result {
                 Ok(_) => 0,
@@ -358,6 +463,54 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_resumeDownload<'local>(
     }
 }
 
+/// JNI 函数: 从 `db_path` 目录恢复进程重启前注册过的下载器
+///
+/// 把每条持久化记录重新塞回 JNI 自己的下载器表，并把 `DOWNLOADER_ID` 推进到
+/// 不小于已恢复的最大 id，避免和新建下载器冲突。调用方需要用返回的 id 列表
+/// 自行调用 `startDownloadById`/`startMultipleDownloadsById` 续传未完成的任务。
+/// 返回恢复出的 id 列表 (JSON 数组字符串)，失败返回 `"[]"`
+#[cfg(feature = "android")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_tthsd_TTHSDLibrary_resumeFromDisk<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: JClass,
+    db_path: JString,
+) -> jstring {
+    let path = match env.get_string(&db_path) {
+        Ok(s) => String::from(s),
+        Err(_) => String::new(),
+    };
+
+    let restored_ids = if path.is_empty() {
+        Vec::new()
+    } else {
+        let entries = super::persistence::load_all(&path);
+        let mut restored_ids = Vec::new();
+
+        let mut id_guard = get_downloader_id().lock().unwrap();
+        let mut downloaders = get_downloaders().lock().unwrap();
+
+        for entry in entries {
+            let config = entry.config.into_download_config();
+            let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
+            downloaders.insert(entry.id, downloader);
+            restored_ids.push(entry.id);
+
+            if entry.id > *id_guard {
+                *id_guard = entry.id;
+            }
+        }
+
+        restored_ids
+    };
+
+    let json = serde_json::to_string(&restored_ids).unwrap_or_else(|_| "[]".to_string());
+    match env.new_string(json) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
 /// JNI 函数: 停止下载
 #[cfg(feature = "android")]
 #[unsafe(no_mangle)]
@@ -373,7 +526,9 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_stopDownload<'local>(
     match downloader {
         Some(d) => {
             let result = RUNTIME.block_on(async {
-                d.read().await.stop_download().await
+                let result = d.read().await.stop_download().await;
+                prune_if_configured(&d, id).await;
+                result
             });
             match result {
                 Ok(_) => 0,
@@ -382,4 +537,49 @@ pub extern "C" fn Java_com_tthsd_TTHSDLibrary_stopDownload<'local>(
         }
         None => -1,
     }
+}
+
+/// JNI 函数: 拉取式查询下载器状态，返回 `StatusSnapshot` 的 JSON 字符串
+///
+/// 配合 `getActiveDownloaderIds` 使用，让 Android Activity 重建后能立刻
+/// 恢复界面状态，不必等待下一条推送回调。id 不存在时返回 `"{}"`。
+#[cfg(feature = "android")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getProgress<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: JClass,
+    id: jint,
+) -> jstring {
+    let downloaders = get_downloaders().lock().unwrap();
+    let downloader = downloaders.get(&id).cloned();
+    drop(downloaders);
+
+    let json = match downloader {
+        Some(d) => {
+            let snapshot = RUNTIME.block_on(async { d.read().await.status.snapshot().await });
+            serde_json::to_string(&snapshot).unwrap_or_else(|_| "{}".to_string())
+        }
+        None => "{}".to_string(),
+    };
+
+    match env.new_string(json) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// JNI 函数: 返回当前仍在表中的下载器 id 列表 (JSON 数组字符串)
+#[cfg(feature = "android")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_tthsd_TTHSDLibrary_getActiveDownloaderIds<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: JClass,
+) -> jstring {
+    let ids: Vec<i32> = get_downloaders().lock().unwrap().keys().copied().collect();
+    let json = serde_json::to_string(&ids).unwrap_or_else(|_| "[]".to_string());
+
+    match env.new_string(json) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::JoinHandle;
+use serde::{Deserialize, Serialize};
+
+/// 下载完成时(或边下边解压时)支持就地解压的归档格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarBz2,
+    TarLz4,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// 根据 URL 后缀猜测归档格式，未识别返回 None
+    pub fn from_url(url: &str) -> Option<Self> {
+        let lower = url.to_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if lower.ends_with(".tar.bz2") || lower.ends_with(".tbz2") {
+            Some(ArchiveFormat::TarBz2)
+        } else if lower.ends_with(".tar.lz4") {
+            Some(ArchiveFormat::TarLz4)
+        } else if lower.ends_with(".tar.zst") || lower.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
+    }
+
+    fn decoder(self) -> Box<dyn ArchiveDecoder> {
+        match self {
+            ArchiveFormat::TarGz => Box::new(TarGzDecoder),
+            ArchiveFormat::TarBz2 => Box::new(TarBz2Decoder),
+            ArchiveFormat::TarLz4 => Box::new(TarLz4Decoder),
+            ArchiveFormat::TarZst => Box::new(TarZstDecoder),
+        }
+    }
+}
+
+/// 从顺序字节流解包归档到目标目录的统一入口
+///
+/// 新增容器/压缩格式只需要新写一个实现并在 `ArchiveFormat::decoder` 里注册一行，
+/// 不需要改动调用方 (`HTTPDownloader`/`spawn_extract_pipeline`)。
+trait ArchiveDecoder {
+    fn unpack(&self, reader: Box<dyn Read>, target_dir: &Path) -> Result<(), String>;
+}
+
+struct TarGzDecoder;
+
+impl ArchiveDecoder for TarGzDecoder {
+    fn unpack(&self, reader: Box<dyn Read>, target_dir: &Path) -> Result<(), String> {
+        let decoder = flate2::read::GzDecoder::new(reader);
+        tar::Archive::new(decoder)
+            .unpack(target_dir)
+            .map_err(|e| format!("解压 tar.gz 失败: {}", e))
+    }
+}
+
+struct TarBz2Decoder;
+
+impl ArchiveDecoder for TarBz2Decoder {
+    fn unpack(&self, reader: Box<dyn Read>, target_dir: &Path) -> Result<(), String> {
+        let decoder = bzip2::read::BzDecoder::new(reader);
+        tar::Archive::new(decoder)
+            .unpack(target_dir)
+            .map_err(|e| format!("解压 tar.bz2 失败: {}", e))
+    }
+}
+
+struct TarLz4Decoder;
+
+impl ArchiveDecoder for TarLz4Decoder {
+    fn unpack(&self, reader: Box<dyn Read>, target_dir: &Path) -> Result<(), String> {
+        let decoder = lz4::Decoder::new(reader)
+            .map_err(|e| format!("创建 lz4 解码器失败: {}", e))?;
+        tar::Archive::new(decoder)
+            .unpack(target_dir)
+            .map_err(|e| format!("解压 tar.lz4 失败: {}", e))
+    }
+}
+
+struct TarZstDecoder;
+
+impl ArchiveDecoder for TarZstDecoder {
+    fn unpack(&self, reader: Box<dyn Read>, target_dir: &Path) -> Result<(), String> {
+        let decoder = zstd::stream::read::Decoder::new(reader)
+            .map_err(|e| format!("创建 zstd 解码器失败: {}", e))?;
+        tar::Archive::new(decoder)
+            .unpack(target_dir)
+            .map_err(|e| format!("解压 tar.zst 失败: {}", e))
+    }
+}
+
+/// 下载线程推送给解压线程的一块原始数据
+pub struct DataChunk {
+    pub offset: i64,
+    pub bytes: Vec<u8>,
+}
+
+/// 解压线程与下载侧之间的有界 channel 容量
+const DECODE_QUEUE_SIZE: usize = 64;
+
+/// 顺序读取适配器
+///
+/// 下载侧的多个线程可能乱序把 `DataChunk` 推进来，这里用一个以 offset 为键的
+/// reorder buffer 暂存提前到达的块，只有轮到 `next_offset` 的块才会喂给解码器，
+/// 从而保证解码器看到的始终是严格递增、无缝的字节流。
+struct OrderedChunkReader {
+    rx: Receiver<DataChunk>,
+    reorder: BTreeMap<i64, Vec<u8>>,
+    next_offset: i64,
+    current: Vec<u8>,
+    cursor: usize,
+}
+
+impl OrderedChunkReader {
+    fn new(rx: Receiver<DataChunk>) -> Self {
+        OrderedChunkReader {
+            rx,
+            reorder: BTreeMap::new(),
+            next_offset: 0,
+            current: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// 取出下一段可读数据，返回 false 表示上游已经关闭且没有更多数据
+    fn fill(&mut self) -> io::Result<bool> {
+        loop {
+            if let Some(bytes) = self.reorder.remove(&self.next_offset) {
+                self.next_offset += bytes.len() as i64;
+                self.current = bytes;
+                self.cursor = 0;
+                return Ok(true);
+            }
+
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    if chunk.offset == self.next_offset {
+                        self.next_offset += chunk.bytes.len() as i64;
+                        self.current = chunk.bytes;
+                        self.cursor = 0;
+                        return Ok(true);
+                    }
+                    self.reorder.insert(chunk.offset, chunk.bytes);
+                }
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+}
+
+impl Read for OrderedChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cursor >= self.current.len() && !self.fill()? {
+            return Ok(0);
+        }
+
+        let available = &self.current[self.cursor..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.cursor += n;
+        Ok(n)
+    }
+}
+
+/// 启动一个专职解压线程
+///
+/// 返回下载侧用来推送 `DataChunk` 的发送端，以及解压线程的 `JoinHandle`。
+/// 调用方把下载得到的字节块连同 offset 发给发送端，解压线程负责重新排序、
+/// 解码并通过 `tar::Archive` 把条目写到 `target_dir`。
+pub fn spawn_extract_pipeline(
+    target_dir: PathBuf,
+    format: ArchiveFormat,
+) -> (SyncSender<DataChunk>, JoinHandle<Result<(), String>>) {
+    let (tx, rx) = sync_channel::<DataChunk>(DECODE_QUEUE_SIZE);
+
+    let handle = std::thread::spawn(move || -> Result<(), String> {
+        let reader: Box<dyn Read> = Box::new(OrderedChunkReader::new(rx));
+        format.decoder().unpack(reader, &target_dir)
+    });
+
+    (tx, handle)
+}
+
+/// 对一个已经完整落盘的文件做解压，用于"下载完成后再解压"的场景
+///
+/// 与 `spawn_extract_pipeline` 不同，这里读到的字节已经是完整顺序的文件，
+/// 不需要 `OrderedChunkReader` 的乱序重排缓冲。
+pub fn extract_completed_file(file_path: &Path, target_dir: &Path, format: ArchiveFormat) -> Result<(), String> {
+    let file = std::fs::File::open(file_path)
+        .map_err(|e| format!("打开待解压文件失败: {}", e))?;
+    std::fs::create_dir_all(target_dir)
+        .map_err(|e| format!("创建解压目标目录失败: {}", e))?;
+    format.decoder().unpack(Box::new(file), target_dir)
+}
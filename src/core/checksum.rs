@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// 支持的文件完整性校验算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgo {
+    Sha256,
+    Blake3,
+}
+
+/// 顺序读取 `path` 并用 `algo` 计算摘要，和 `expected` (十六进制，大小写不敏感) 比较
+///
+/// 分块下载的各个 worker 是乱序写入不相交的偏移区间的，因此校验只能在下载完成后
+/// 对落盘文件做一次完整的顺序重读，不能在写入过程中增量计算。
+pub async fn verify_file(path: &str, algo: HashAlgo, expected: &str) -> Result<(), String> {
+    let path = path.to_string();
+    let expected = expected.to_lowercase();
+
+    let actual = tokio::task::spawn_blocking(move || -> Result<String, String> {
+        use std::io::Read;
+
+        let mut file = std::fs::File::open(&path).map_err(|e| format!("打开文件失败: {}", e))?;
+        let mut buf = vec![0u8; 1024 * 1024];
+
+        match algo {
+            HashAlgo::Sha256 => {
+                use sha2::{Digest, Sha256};
+                let mut hasher = Sha256::new();
+                loop {
+                    let n = file.read(&mut buf).map_err(|e| format!("读取文件失败: {}", e))?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+                Ok(format!("{:x}", hasher.finalize()))
+            }
+            HashAlgo::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                loop {
+                    let n = file.read(&mut buf).map_err(|e| format!("读取文件失败: {}", e))?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+                Ok(hasher.finalize().to_hex().to_string())
+            }
+        }
+    })
+    .await
+    .map_err(|e| format!("校验任务 join 失败: {:?}", e))??;
+
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("文件校验失败: 期望 {}, 实际 {}", expected, actual))
+    }
+}
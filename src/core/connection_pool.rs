@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// 一条空闲连接及其被放回池子的时间，用来做 idle 超时淘汰
+struct Idle<T> {
+    conn: T,
+    since: Instant,
+}
+
+/// 按任意 Key（FTP/SFTP 场景下是 `(host, port, user)`）分桶的通用连接池
+///
+/// 建模自 OpenDAL 的 bb8 FTP 连接池：同一个目标的重复下载复用已经三次握手 +
+/// 登录好的会话，而不是每次都重新建连；取出的连接归调用方负责用一次轻量操作
+/// （NOOP/stat 之类）校验，校验不过就不归还、让调用方重新建连，池子本身不替
+/// 调用方做校验，因为不同协议的"探活"方式不一样。
+///
+/// `max_size`/`idle_timeout` 不固定在池子上，而是每次 `acquire`/`release` 传入：
+/// 池子是进程级别的全局单例（见各协议模块里的 `once_cell::sync::Lazy`），
+/// 但这两个参数来自每个任务自己的 `DownloadConfig`，所以留给调用方按当次配置传。
+pub struct ConnPool<K, T> {
+    buckets: Mutex<HashMap<K, Vec<Idle<T>>>>,
+}
+
+impl<K, T> ConnPool<K, T>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        ConnPool {
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取出一条还没超过 `idle_timeout` 的空闲连接；超时的连接直接丢弃而不是归还给调用方，
+    /// 池子里没有可用连接时返回 `None`，调用方应该自己新建一条
+    pub async fn acquire(&self, key: &K, idle_timeout: Duration) -> Option<T>
+    where
+        K: Clone,
+    {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.get_mut(key)?;
+
+        while let Some(idle) = bucket.pop() {
+            if idle.since.elapsed() < idle_timeout {
+                return Some(idle.conn);
+            }
+        }
+        None
+    }
+
+    /// 把校验通过、可以复用的连接放回池子；桶里已经有 `max_size` 条就直接丢弃多出来的
+    /// 连接（让它自然 drop/断开），不是池子越大越好
+    pub async fn release(&self, key: K, conn: T, max_size: usize) {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.entry(key).or_insert_with(Vec::new);
+        if bucket.len() < max_size {
+            bucket.push(Idle { conn, since: Instant::now() });
+        }
+    }
+}
+
+/// `DownloadConfig::conn_pool_max_size` 未显式设置时的默认每目标连接数上限
+pub const DEFAULT_CONN_POOL_MAX_SIZE: usize = 4;
+
+/// `DownloadConfig::conn_pool_idle_timeout_secs` 未显式设置时的默认空闲超时
+pub const DEFAULT_CONN_POOL_IDLE_TIMEOUT_SECS: u64 = 60;
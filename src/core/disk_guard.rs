@@ -0,0 +1,89 @@
+use std::path::Path;
+
+/// 下载开始前的容量检查: 根据已知的总大小和目标路径所在文件系统的剩余空间，
+/// 提前拒绝明显装不下的下载，而不是写到一半才因为磁盘满失败。
+/// `total_size` 未知时直接放行，调用方应在探测到大小后尽早调用本函数。
+pub fn check_capacity(save_path: &str, total_size: i64) -> Result<(), String> {
+    if total_size <= 0 {
+        return Ok(());
+    }
+    let total_size = total_size as u64;
+
+    let dir = Path::new(save_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+
+    match available_space(&dir) {
+        Some(available) if available < total_size => Err(format!(
+            "目标磁盘剩余空间不足: 需要 {} bytes，剩余 {} bytes ({})",
+            total_size, available, dir.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 把文件预分配到 `size` 字节: 优先用 `fallocate`（Linux）一次性分配物理块，减少碎片并让
+/// 分段/续传写入可以放心 seek 到任意偏移；平台不支持或分配失败（例如 tmpfs/FAT32）时
+/// 退化为 `set_len`（只扩展逻辑大小，不保证物理分配）。
+pub async fn preallocate(file: &tokio::fs::File, size: u64) -> Result<(), String> {
+    if try_fallocate(file, size) {
+        return Ok(());
+    }
+    file.set_len(size).await.map_err(|e| format!("预分配文件大小失败: {}", e))
+}
+
+#[cfg(target_os = "linux")]
+fn try_fallocate(file: &tokio::fs::File, size: u64) -> bool {
+    use std::os::unix::io::AsRawFd;
+    let fd = file.as_raw_fd();
+    unsafe { libc::fallocate(fd, 0, 0, size as libc::off_t) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn try_fallocate(_file: &tokio::fs::File, _size: u64) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    let ret = unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) };
+    if ret != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let mut wide: Vec<u16> = dir.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut free_bytes_available: u64 = 0;
+    let ret = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_mut_ptr(),
+            &mut free_bytes_available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ret == 0 {
+        return None;
+    }
+    Some(free_bytes_available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+/// 一个分块 worker 在续传日志里记录的状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerProgress {
+    pub start_pos: i64,
+    pub progress: i64,
+    pub end_pos: i64,
+}
+
+/// 断点续传用的分块下载进度日志，落盘为 `{save_path}.part.json`
+///
+/// `validator` 记录发起下载时服务器返回的 ETag（没有则退化为 Last-Modified），
+/// 续传前会拿一次新的 HEAD 响应核对，只有 validator 和 `total_size` 都没变
+/// 才信任日志里的进度，否则视为远端文件已经变化，丢弃日志重新下载。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadJournal {
+    pub total_size: i64,
+    pub validator: Option<String>,
+    pub workers: Vec<WorkerProgress>,
+}
+
+fn journal_path(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part.json", save_path))
+}
+
+fn journal_tmp_path(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part.json.tmp", save_path))
+}
+
+impl DownloadJournal {
+    /// 读取续传日志；日志缺失或损坏都视为没有可续传的进度
+    pub fn load(save_path: &str) -> Option<Self> {
+        let data = std::fs::read(journal_path(save_path)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// 只有 `total_size`/`validator` 都和这次的远端信息匹配才认为日志仍然有效
+    pub fn matches(&self, total_size: i64, validator: &Option<String>) -> bool {
+        self.total_size == total_size && &self.validator == validator
+    }
+
+    /// 原子写入日志：先写临时文件再 rename，避免进程崩溃在写一半时留下损坏的日志
+    pub fn save(&self, save_path: &str) -> std::io::Result<()> {
+        let data = serde_json::to_vec(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let tmp_path = journal_tmp_path(save_path);
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, journal_path(save_path))?;
+        Ok(())
+    }
+
+    /// 下载成功完成后清理日志
+    pub fn delete(save_path: &str) {
+        let _ = std::fs::remove_file(journal_path(save_path));
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+use super::get_downloader::get_downloader;
+use super::downloader_interface::Downloader;
+use super::scheduler::DownloadScheduler;
+use super::send_message::send_message;
+use super::socket_client::SocketClient;
+use super::websocket_client::WebSocketClient;
+use super::archive_extractor::ArchiveFormat;
+use super::checksum::HashAlgo;
+use super::status_snapshot::{Phase, StatusTracker};
+use super::torrent_downloader::SeedConfig;
+
+/// 默认 User-Agent，任务未单独指定时使用
+pub const UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+
+/// 分块下载失败后的默认最大重试次数
+pub const DEFAULT_MAX_RETRIES: usize = 5;
+
+/// 批量下载时默认同时运行的任务数上限
+pub const DEFAULT_MAX_CONCURRENT_TASKS: usize = 3;
+
+/// 重试预算耗尽前默认允许的最长累计等待时间（秒），超过后放弃重试
+pub const DEFAULT_MAX_RETRY_ELAPSED_SECS: u64 = 60;
+
+/// ED2K 链接默认使用的 ED2K→HTTP 网关列表，失败时按顺序轮换
+pub fn default_ed2k_gateways() -> Vec<String> {
+    vec!["https://ed2k.lyoko.io/hash".to_string()]
+}
+
+/// WebSocket 进度消息的编码方式，双方需要在建立连接前约定好
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WsCodec {
+    /// 人类可读的 JSON 文本帧，兼容所有现有消费者，是默认值
+    Json,
+    /// 用 `rmp-serde` 编码为 MessagePack 二进制帧，体积更小、分配更少，
+    /// 适合高线程数下载产生的大量 Update 进度消息
+    MessagePack,
+}
+
+/// `DownloadConfig::ws_codec` 未显式设置时使用的默认编码
+pub fn default_ws_codec() -> WsCodec {
+    WsCodec::Json
+}
+
+/// `DownloadConfig::conn_pool_max_size` 未显式设置时的默认值
+pub fn default_conn_pool_max_size() -> usize {
+    super::connection_pool::DEFAULT_CONN_POOL_MAX_SIZE
+}
+
+/// `DownloadConfig::conn_pool_idle_timeout_secs` 未显式设置时的默认值
+pub fn default_conn_pool_idle_timeout_secs() -> u64 {
+    super::connection_pool::DEFAULT_CONN_POOL_IDLE_TIMEOUT_SECS
+}
+
+/// 进度回调函数签名: callback(event_json, data_json)
+pub type ProgressCallback = extern "C" fn(*const std::os::raw::c_char, *const std::os::raw::c_char);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadTask {
+    pub url: String,
+    pub save_path: String,
+    /// 额外的镜像 URL 列表，启用多镜像故障转移时与 `url` 一起轮询使用
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// 下载完成后用于校验文件完整性的期望摘要 (算法, 十六进制摘要)，None 表示不校验
+    #[serde(default)]
+    pub expected_checksum: Option<(HashAlgo, String)>,
+    /// 边下边解压此任务的归档格式，None 表示落盘为普通文件；
+    /// 优先于 `DownloadConfig::extract`，使同一批任务里不同条目可以分别决定要不要解压
+    #[serde(default)]
+    pub extract: Option<ArchiveFormat>,
+    /// BT 任务只下载这些文件索引（对应 `TorrentDownloader::list_files` 返回的 `index`），
+    /// None 表示下载种子里的全部文件；对非 BT 任务无意义
+    #[serde(default)]
+    pub wanted_file_indices: Option<Vec<usize>>,
+    /// FTP 任务启用 TLS (FTPS)；`url` 以 `ftps://` 开头时自动为 true，
+    /// 普通 `ftp://` 想强制升级到 TLS 时也可以显式设置，对 SFTP/其它协议无意义
+    #[serde(default)]
+    pub ftp_secure: bool,
+    /// 仅在 `ftp_secure` 为 true 时生效: 使用隐式 TLS（连接建立即握手，常见端口 990）
+    /// 而不是默认的显式 AUTH TLS（先用明文控制连接登录协商，再升级加密）
+    #[serde(default)]
+    pub ftp_implicit_tls: bool,
+    /// FTPS TLS 握手的超时时间（秒），None 使用 `ftp_downloader::DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS`；
+    /// 避免服务器不响应握手时把 `spawn_blocking` 线程永久卡住
+    #[serde(default)]
+    pub ftp_tls_timeout_secs: Option<u64>,
+    /// SFTP 私钥文件路径；设置时优先尝试公钥认证，未设置才回退到密码认证
+    #[serde(default)]
+    pub sftp_private_key_path: Option<String>,
+    /// 私钥口令，私钥本身未加密时留空即可
+    #[serde(default)]
+    pub sftp_private_key_passphrase: Option<String>,
+    /// SFTP 主机密钥校验用的 known_hosts 文件路径，和 `sftp_pinned_fingerprint` 二选一
+    #[serde(default)]
+    pub sftp_known_hosts_path: Option<String>,
+    /// 钉死的主机密钥指纹（形如 `SHA256:xxxx`），和 `sftp_known_hosts_path` 二选一，
+    /// 两者都没给出时未知主机会被拒绝，除非显式设置 `sftp_insecure`
+    #[serde(default)]
+    pub sftp_pinned_fingerprint: Option<String>,
+    /// 显式跳过 SFTP 主机密钥校验；默认 false，不设置的话未知主机会被拒绝以防中间人攻击
+    #[serde(default)]
+    pub sftp_insecure: bool,
+    /// SFTP 并发分段下载的分段数；None 使用 `sftp_downloader::DEFAULT_SFTP_SEGMENTS`，
+    /// 设为 0 或 1 等价于禁用分段、回退到单通道顺序下载
+    #[serde(default)]
+    pub sftp_segments: Option<usize>,
+    /// SFTP 每个分段读写的缓冲区大小（KB），None 使用 `sftp_downloader::DEFAULT_SFTP_SEGMENT_BUFFER_KB`
+    #[serde(default)]
+    pub sftp_segment_buffer_kb: Option<usize>,
+    /// `url` 指向的 FTP/SFTP 远程路径是目录时，只下载相对路径匹配这个 glob
+    /// （`*`/`?` 通配）的文件；None 表示不过滤，下载目录下的全部文件
+    #[serde(default)]
+    pub dir_include_glob: Option<String>,
+    /// 递归下载目录时，相对路径匹配这个 glob 的文件会被跳过；优先级高于 `dir_include_glob`
+    #[serde(default)]
+    pub dir_exclude_glob: Option<String>,
+    /// 递归下载目录时是否跟随符号链接；默认 false，只下载常规文件，跳过符号链接，
+    /// 避免链接指到目录树外、或者指向自身/父目录造成死循环
+    #[serde(default)]
+    pub dir_follow_symlinks: bool,
+}
+
+impl DownloadTask {
+    /// `url` 加上 `mirrors` 去重后的完整镜像列表，顺序保留 `url` 在前
+    pub fn all_mirrors(&self) -> Vec<String> {
+        let mut urls = Vec::with_capacity(1 + self.mirrors.len());
+        urls.push(self.url.clone());
+        for m in &self.mirrors {
+            if !urls.contains(m) {
+                urls.push(m.clone());
+            }
+        }
+        urls
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadChunk {
+    pub start_offset: i64,
+    pub end_offset: i64,
+    pub done: bool,
+}
+
+#[derive(Clone)]
+pub struct DownloadConfig {
+    pub tasks: Vec<DownloadTask>,
+    pub thread_count: usize,
+    pub chunk_size_mb: usize,
+    pub callback_func: Option<ProgressCallback>,
+    pub use_callback_url: bool,
+    pub callback_url: Option<String>,
+    pub use_socket: Option<bool>,
+    pub show_name: String,
+    pub user_agent: String,
+    /// 下载完成后（或下载过程中）按此格式就地解压，None 表示落盘为普通文件
+    pub extract: Option<ArchiveFormat>,
+    /// 持久化存储目录，设置后下载器状态会在 create/start/pause/stop 时写入该目录，
+    /// 以便进程崩溃或重启后可以通过 `restore_downloads` 恢复
+    pub db_path: Option<String>,
+    /// 限速上限，单位字节/秒；None 表示不限速
+    pub max_speed_bps: Option<u64>,
+    /// 单个分块连续失败后的最大重试次数（不含首次尝试），超过后才真正判定为错误
+    pub max_retries: usize,
+    /// 批量下载 (`start_multiple_downloads`) 时同时运行的任务数上限；
+    /// 单连接配额由 `scheduler::global_connection_semaphore` 统一控制
+    pub max_concurrent_tasks: usize,
+    /// 指数退避重试预算耗尽前允许的最长累计等待时间（秒），和 `max_retries` 任一项
+    /// 先用完都会放弃重试
+    pub max_retry_elapsed_secs: u64,
+    /// ED2K 链接可用的 ED2K→HTTP 网关列表，按顺序轮询，一个网关连续失败多次后会被跳过
+    pub ed2k_gateways: Vec<String>,
+    /// 回调 WebSocket 推送进度消息时使用的编码，默认 JSON
+    pub ws_codec: WsCodec,
+    /// 回调 WebSocket 鉴权令牌，设置后 `WebSocketClient` 会在握手后先发送
+    /// `ConnectionInit` 消息并等待服务端 ACK，在此之前不会标记为已连接；
+    /// None 表示不鉴权，保持和旧版本一致的行为
+    pub auth_token: Option<String>,
+    /// BT 任务下载完成后继续做种的配置，None 表示下载完成立即断开、不回馈种群
+    pub seed: Option<SeedConfig>,
+    /// BT 会话状态目录，设置后 librqbit 会把种子元数据和分片位图持久化到此处，
+    /// 进程重启后可通过 `TorrentDownloader::resume_all` 恢复，而不必重新校验/下载；
+    /// None 表示会话状态只留在内存里，随进程退出而丢失
+    pub torrent_session_dir: Option<String>,
+    /// 同一个 (host, port, user) 目标在 FTP/SFTP 连接池里最多保留的空闲连接数
+    pub conn_pool_max_size: usize,
+    /// FTP/SFTP 连接池里的连接空闲超过这么多秒就不再被复用，取出时直接丢弃重连
+    pub conn_pool_idle_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EventType {
+    Start,
+    Update,
+    Pause,
+    Resume,
+    Stop,
+    Done,
+    Err,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(rename = "Type")]
+    pub event_type: EventType,
+    pub name: String,
+    pub show_name: String,
+    pub id: String,
+}
+
+/// 通过命令队列异步下发的控制指令
+/// FFI 一侧只需要把命令塞进 `command_tx` 就立刻返回，真正的暂停/恢复/停止
+/// 逻辑由持有 `command_rx` 的后台循环消费，避免在调用线程上 `block_on`。
+#[derive(Debug, Clone)]
+pub enum DownloaderCommand {
+    Pause,
+    Resume,
+    Stop,
+    /// 运行时调整批量下载的并发线程数，来源于远端控制指令 (见 `websocket_client`)
+    SetThreadCount(usize),
+    /// 下载任务已经自然结束（成功或出错），只用来让命令循环退出，不触发任何事件
+    Shutdown,
+}
+
+/// 下载任务的顶层句柄
+/// 持有共享配置以及可选的 WebSocket/Socket 推送客户端，
+/// 实际的协议相关下载逻辑委托给 `get_downloader` 返回的 `Downloader` 实现。
+pub struct HSDownloader {
+    pub config: Arc<RwLock<DownloadConfig>>,
+    pub ws_client: Option<Arc<tokio::sync::Mutex<WebSocketClient>>>,
+    pub socket_client: Option<Arc<tokio::sync::Mutex<SocketClient>>>,
+    /// 拉取式状态查询用的快照，供 `get_download_status` FFI 读取
+    pub status: Arc<StatusTracker>,
+    /// 控制命令发送端，供 FFI/IPC 推送 `Pause`/`Resume`/`Stop` 而不阻塞调用方
+    pub command_tx: mpsc::UnboundedSender<DownloaderCommand>,
+    /// 接收端只能被取走一次，交给注册下载器时启动的后台命令循环消费
+    command_rx: Mutex<Option<mpsc::UnboundedReceiver<DownloaderCommand>>>,
+    /// `start_download` 运行期间持有的单任务取消句柄，在 `download()` 拿到
+    /// `&mut self` 之前就从 `Downloader::running_handle()` 克隆出来，
+    /// `pause_download`/`stop_download` 直接翻转它，不需要再去抢下载器本身的锁
+    /// ——`download()` 全程持有 `&mut self` 直到看到 `running == false` 才返回，
+    /// 在这里再去抢只会和它自己死等，见 `cancel_active` 的文档
+    active_cancel: RwLock<Option<Arc<AtomicBool>>>,
+    /// `start_download` 运行期间持有的单任务并发线程数句柄，同样在 `download()`
+    /// 拿到 `&mut self` 之前就从 `Downloader::thread_count_handle()` 克隆出来，
+    /// `apply_thread_count` 直接写它，原理同上
+    active_thread_count: RwLock<Option<Arc<AtomicUsize>>>,
+    /// `start_multiple_downloads` 运行期间持有的调度器句柄，语义同上，
+    /// 一次 `cancel_all` 能叫停批量下载里所有仍在运行的任务
+    active_scheduler: RwLock<Option<Arc<DownloadScheduler>>>,
+}
+
+impl HSDownloader {
+    pub fn new(config: DownloadConfig) -> Self {
+        let callback_url = config.callback_url.clone();
+        let use_socket = config.use_socket.unwrap_or(false);
+
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+
+        // 把 command_tx 一并交给 WebSocketClient，让远端通过回调 socket 发回的
+        // Pause/Resume/Stop 控制指令能直接投递到和 FFI/IPC 共用的同一条命令队列
+        let ws_codec = config.ws_codec;
+        let auth_token = config.auth_token.clone();
+        let ws_client = if config.use_callback_url && !use_socket {
+            callback_url.clone().map(|url| Arc::new(tokio::sync::Mutex::new(WebSocketClient::new(url, Some(command_tx.clone()), ws_codec, auth_token))))
+        } else {
+            None
+        };
+
+        let socket_client = if config.use_callback_url && use_socket {
+            callback_url.map(|url| Arc::new(tokio::sync::Mutex::new(SocketClient::new(url))))
+        } else {
+            None
+        };
+
+        HSDownloader {
+            config: Arc::new(RwLock::new(config)),
+            ws_client,
+            socket_client,
+            status: Arc::new(StatusTracker::new()),
+            command_tx,
+            command_rx: Mutex::new(Some(command_rx)),
+            active_cancel: RwLock::new(None),
+            active_thread_count: RwLock::new(None),
+            active_scheduler: RwLock::new(None),
+        }
+    }
+
+    /// 取走命令接收端，交给调用方在后台循环里消费；重复调用只有第一次能拿到 `Some`
+    pub async fn take_command_receiver(&self) -> Option<mpsc::UnboundedReceiver<DownloaderCommand>> {
+        self.command_rx.lock().await.take()
+    }
+
+    async fn send_event(&self, event_type: EventType, name: &str) {
+        let show_name = self.config.read().await.show_name.clone();
+        let event = Event {
+            event_type,
+            name: name.to_string(),
+            show_name,
+            id: String::new(),
+        };
+        let _ = send_message(event, HashMap::new(), &self.config, &self.ws_client, &self.socket_client).await;
+    }
+
+    pub async fn start_download(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let task = {
+            let cfg = self.config.read().await;
+            cfg.tasks.first().cloned().ok_or("没有可下载的任务")?
+        };
+
+        self.send_event(EventType::Start, "开始下载").await;
+        self.status.set_phase(Phase::Running).await;
+        self.status.inc_active_threads();
+
+        let mut downloader = get_downloader(self.config.clone()).await;
+        // 必须在调用 `download()` 拿到它的 `&mut` 之前克隆这两个句柄，否则
+        // `cancel_active`/`apply_thread_count` 除了等 `download()` 跑完没有别的
+        // 办法拿到同一个下载器，而它只有看见 `running == false` 才会跑完——
+        // 自己把自己锁死
+        *self.active_cancel.write().await = Some(downloader.running_handle());
+        *self.active_thread_count.write().await = Some(downloader.thread_count_handle());
+
+        let result = downloader.download(&task).await;
+
+        *self.active_cancel.write().await = None;
+        *self.active_thread_count.write().await = None;
+        self.status.dec_active_threads();
+        match &result {
+            Ok(_) => {
+                self.status.set_phase(Phase::Done).await;
+                self.send_event(EventType::Done, "下载完成").await;
+            }
+            Err(e) => {
+                self.status.set_phase(Phase::Error).await;
+                self.send_event(EventType::Err, &e.to_string()).await;
+            }
+        }
+
+        result
+    }
+
+    pub async fn start_multiple_downloads(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (tasks, max_concurrent_tasks) = {
+            let cfg = self.config.read().await;
+            (cfg.tasks.clone(), cfg.max_concurrent_tasks)
+        };
+
+        self.send_event(EventType::Start, "开始批量下载").await;
+        self.status.set_phase(Phase::Running).await;
+
+        // 用调度器代替逐个串行下载: 任务级并发数由 `max_concurrent_tasks` 限制，
+        // 连接级配额由所有任务共享的 `scheduler::global_connection_semaphore` 统一控制，
+        // 避免 N 个任务同时跑时连接数被乘到 N 倍
+        let scheduler = Arc::new(DownloadScheduler::new(self.config.clone(), tasks, max_concurrent_tasks));
+        *self.active_scheduler.write().await = Some(scheduler.clone());
+
+        self.status.inc_active_threads();
+        let results = scheduler.run().await;
+        self.status.dec_active_threads();
+        *self.active_scheduler.write().await = None;
+
+        for (_task, result) in &results {
+            if result.is_ok() {
+                self.status.inc_completed_chunks();
+            }
+        }
+
+        if let Some((_, Err(e))) = results.into_iter().find(|(_, r)| r.is_err()) {
+            self.status.set_phase(Phase::Error).await;
+            self.send_event(EventType::Err, &e).await;
+            return Err(e.into());
+        }
+
+        self.status.set_phase(Phase::Done).await;
+        self.send_event(EventType::Done, "全部下载完成").await;
+        Ok(())
+    }
+
+    /// 取消当前真正在跑的下载器/调度器（如果有），让传输循环在下一次 chunk
+    /// 边界检查点看到 `running == false` 后自行退出；单任务和批量两种模式
+    /// 同一时刻只会有一个是 `Some`。直接翻转 `active_cancel` 这个
+    /// `Arc<AtomicBool>`，不经过下载器本身的锁——那把锁要等 `download()` 看到
+    /// `running == false` 才会释放，在这里再去抢只会和它互相死等。
+    async fn cancel_active(&self) {
+        if let Some(flag) = self.active_cancel.read().await.clone() {
+            flag.store(false, Ordering::Relaxed);
+        }
+        if let Some(scheduler) = self.active_scheduler.read().await.clone() {
+            scheduler.cancel_all().await;
+        }
+    }
+
+    /// 暂停下载：不只是把 `StatusTracker` 的 `Phase` 改成 `Paused`，而是真正
+    /// 取消正在跑的传输循环，让它在下一次 chunk 边界退出。FTP/SFTP/HTTP 的
+    /// 传输循环在收到取消信号时会把已下载的字节数写回断点续传状态
+    /// （`.resume.json` / `DownloadJournal`），所以之后重新调用
+    /// `start_download`/`start_multiple_downloads` 能从暂停点继续，而不是
+    /// 重新下载整个文件——这就是这里没有单独的"恢复正在跑的传输"机制的原因。
+    pub async fn pause_download(&self) {
+        self.cancel_active().await;
+        self.status.set_phase(Phase::Paused).await;
+        self.send_event(EventType::Pause, "已暂停").await;
+    }
+
+    /// 恢复下载：由于取消是单向操作（传输循环退出后对应的下载任务即告结束），
+    /// 这里不保留一个可以原地接回去的"挂起中"下载器，只把状态标记回
+    /// `Running` 并广播事件。真正让下载继续，需要调用方重新调用
+    /// `start_download`/`start_multiple_downloads`——凭借 FTP/SFTP/HTTP 在
+    /// `pause_download` 时已经落盘的断点续传状态，下载会从上次取消的位置
+    /// 接着跑，而不是从头开始。
+    pub async fn resume_download(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.status.set_phase(Phase::Running).await;
+        self.send_event(EventType::Resume, "已恢复").await;
+        Ok(())
+    }
+
+    /// 停止下载：真正取消正在跑的下载器/调度器，而不是只把 `Phase` 改成
+    /// `Done`——否则调用方以为下载已经终止，实际上传输还会在后台跑到完成。
+    pub async fn stop_download(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.cancel_active().await;
+        self.status.set_phase(Phase::Done).await;
+        self.send_event(EventType::Stop, "已停止").await;
+        Ok(())
+    }
+
+    /// 把 `SetThreadCount` 控制指令转发给当前真正在跑的下载器/调度器（如果有），
+    /// 让并发线程数调整对一个正在跑的下载实际生效，而不是只写进 `DownloadConfig`
+    /// 等下一次下载才读取到。直接写 `active_thread_count` 这个
+    /// `Arc<AtomicUsize>`，不经过下载器本身。只有 HTTP 下载器的动态分片重分配
+    /// 循环会真正读取它，其它协议调用这个方法没有效果。
+    pub async fn apply_thread_count(&self, count: usize) {
+        if let Some(handle) = self.active_thread_count.read().await.clone() {
+            handle.store(count.max(1), Ordering::Relaxed);
+        }
+        if let Some(scheduler) = self.active_scheduler.read().await.clone() {
+            scheduler.set_thread_count_all(count).await;
+        }
+    }
+}
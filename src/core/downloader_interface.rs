@@ -1,5 +1,6 @@
 use std::time::Instant;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use tokio::sync::RwLock;
 use super::websocket_client::WebSocketClient;
 use super::socket_client::SocketClient;
@@ -9,8 +10,27 @@ use super::downloader::{DownloadChunk, DownloadConfig, DownloadTask};
 pub trait Downloader: Send + Sync {
     async fn download(&mut self, task: &DownloadTask) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
     fn get_type(&self) -> String;
-    async fn cancel(&mut self, downloader: Box<dyn Downloader>);
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>>;
+    /// 取消信号的句柄：在 `download()` 拿到 `&mut self` 之前就克隆出来交给调用方
+    /// （`HSDownloader`/`DownloadScheduler`），对方之后直接 `store(false, ..)`
+    /// 这个 `Arc<AtomicBool>`，完全不需要再去抢 `download()` 全程持有的那把锁。
+    /// 每个实现都只是 `self.base.running.clone()`——这正是 `running` 字段本身
+    /// 文档写的设计意图；此前 `cancel(&mut self, ..)` 需要和 `download()` 抢同一个
+    /// `&mut self`，`HSDownloader`/`DownloadScheduler` 又把它包进
+    /// `Arc<RwLock<Box<dyn Downloader>>>` 再用 `.write().await` 去调，等于把锁又
+    /// 绕了回来，和 `download()` 自己持有的写锁互相死等。
+    fn running_handle(&self) -> Arc<AtomicBool>;
+    /// 并发线程数上限的句柄，同样在 `download()` 拿到 `&mut self` 之前就克隆出来
+    /// 交给调用方，原理和 `running_handle` 一样：不这样做的话，`SetThreadCount`
+    /// 控制指令就得像旧的 `set_thread_count(&self, ..)` 那样通过
+    /// `Arc<RwLock<Box<dyn Downloader>>>` 的 `.read()`/`.write()` 重新去抢
+    /// `download()` 全程持有的那把锁，在单任务下载进行中会和 `download()` 互相死等。
+    /// 每个实现都只是 `self.base.thread_count.clone()`。只有 `HTTPDownloader` 的
+    /// 动态分片重分配循环会真正读取这个 `Arc<AtomicUsize>`；其它协议（包括
+    /// Metalink——它的分段 worker 在下载一开始就全部 spawn 完了，没有运行时能调整
+    /// 的挂钩点）拿到这个句柄也不会有实际效果，`SetThreadCount` 对它们的在途下载
+    /// 没有效果，只会影响下一次下载使用的 `thread_count`
+    fn thread_count_handle(&self) -> Arc<AtomicUsize>;
 }
 
 pub struct BaseDownloader {
@@ -22,7 +42,18 @@ pub struct BaseDownloader {
     pub ws_client: Option<Arc<tokio::sync::Mutex<WebSocketClient>>>,
     pub socket_client: Option<Arc<tokio::sync::Mutex<SocketClient>>>,
     pub config: Option<Arc<RwLock<DownloadConfig>>>,
-    pub running: bool,
+    /// 取消标记：包成 `Arc<AtomicBool>` 而不是普通 `bool`，这样取消不需要拿到和
+    /// 正在跑的 `download()` 同一个 `&mut self`（那样会被借用检查器卡死，下载不
+    /// 完成就永远拿不到锁）——调用方在 `download()` 开始之前就通过
+    /// `Downloader::running_handle()` 克隆一份这个 `Arc`，下载进行中直接翻转它，
+    /// 由传输循环自己每轮检查
+    pub running: Arc<AtomicBool>,
+    /// 当前生效的并发线程数上限，同样包成 `Arc<AtomicUsize>` 以便不需要 `&mut self`
+    /// 就能在下载进行中被调用方通过 `Downloader::thread_count_handle()` 拿到的
+    /// 句柄直接写入；`download()` 开始时从 `DownloadConfig::thread_count` 初始化，
+    /// 之后收到 `SetThreadCount` 控制指令会原地更新，由支持动态分片的下载器
+    /// 自己的重分配循环每轮读取
+    pub thread_count: Arc<AtomicUsize>,
 }
 
 impl BaseDownloader {
@@ -36,15 +67,12 @@ impl BaseDownloader {
             ws_client: None,
             socket_client: None,
             config: None,
-            running: true,
+            running: Arc::new(AtomicBool::new(true)),
+            thread_count: Arc::new(AtomicUsize::new(1)),
         }
     }
 
-    pub async fn cancel_base(&mut self, _downloader: Box<dyn Downloader>) {
-        self.running = false;
-    }
-
     pub async fn get_snapshot_base(&self) -> Option<Box<dyn std::any::Any>> {
         None
     }
-}
\ No newline at end of file
+}
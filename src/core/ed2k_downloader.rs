@@ -1,15 +1,92 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use md4::{Digest, Md4};
 use tokio::sync::RwLock;
 
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
+use super::mirror_pool::MirrorPool;
 use super::performance_monitor::PerformanceMonitor;
+use super::resumable_download::{ResumableFile, ResumeState};
+use super::retry::{self, NonRetryableError};
+
+/// ED2K 分片大小: 9500 KiB，eMule/ED2K 协议固定值
+const ED2K_CHUNK_SIZE: u64 = 9_728_000;
+
+/// 单个 ED2K 网关连续失败超过这个次数后被永久放弃，改用下一个
+const ED2K_GATEWAY_DROP_THRESHOLD: i64 = 3;
+
+/// 流式计算 ED2K 哈希: 按 9500 KiB 切分分片，每个分片算 MD4，
+/// 多个分片时再把分片摘要拼起来整体算一次 MD4
+struct Ed2kHasher {
+    current_chunk: Md4,
+    current_chunk_len: u64,
+    chunk_digests: Vec<Vec<u8>>,
+}
+
+impl Ed2kHasher {
+    fn new() -> Self {
+        Ed2kHasher {
+            current_chunk: Md4::new(),
+            current_chunk_len: 0,
+            chunk_digests: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let remaining = (ED2K_CHUNK_SIZE - self.current_chunk_len) as usize;
+            let take = remaining.min(data.len());
+            self.current_chunk.update(&data[..take]);
+            self.current_chunk_len += take as u64;
+            data = &data[take..];
+
+            if self.current_chunk_len == ED2K_CHUNK_SIZE {
+                self.finish_current_chunk();
+            }
+        }
+    }
+
+    fn finish_current_chunk(&mut self) {
+        let digest = std::mem::replace(&mut self.current_chunk, Md4::new()).finalize();
+        self.chunk_digests.push(digest.to_vec());
+        self.current_chunk_len = 0;
+    }
+
+    /// 收尾并返回小写十六进制哈希
+    ///
+    /// 关键边界情况: 文件大小恰好是分片大小的整数倍时，eMule 会在最后一轮前
+    /// 追加一个空分片 (MD4(空输入))，再参与最终拼接哈希，否则校验永远对不上。
+    fn finalize_hex(mut self, total_size: u64) -> String {
+        if self.current_chunk_len > 0 {
+            self.finish_current_chunk();
+        }
+
+        if total_size > 0 && total_size % ED2K_CHUNK_SIZE == 0 {
+            self.chunk_digests.push(Md4::new().finalize().to_vec());
+        }
+
+        let final_digest = match self.chunk_digests.len() {
+            0 => Md4::new().finalize().to_vec(),
+            1 => self.chunk_digests.remove(0),
+            _ => {
+                let mut hasher = Md4::new();
+                for digest in &self.chunk_digests {
+                    hasher.update(digest);
+                }
+                hasher.finalize().to_vec()
+            }
+        };
+
+        final_digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
 
 /// ED2K 下载器
 ///
 /// 解析 ed2k://|file|<name>|<size>|<hash>|/ 格式的链接
-/// 通过公共 HTTP 网关将 ED2K 转为 HTTP 下载
-/// 使用网关: https://ed2k.lyoko.io/hash/<hash>
+/// 通过公共 ED2K→HTTP 网关下载；网关列表来自 `DownloadConfig::ed2k_gateways`，
+/// 某个网关连续失败后自动轮换到下一个，直到所有网关都被放弃
 pub struct ED2KDownloader {
     base: BaseDownloader,
     monitor: Option<Arc<PerformanceMonitor>>,
@@ -28,7 +105,7 @@ impl ED2KDownloader {
         ED2KDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
@@ -107,56 +184,145 @@ impl Downloader for ED2KDownloader {
             monitor.set_total_bytes(ed2k_info.size as i64);
         }
 
-        // 构建 HTTP 网关 URL（lyoko.io ED2K 网关）
-        let gateway_url = format!("https://ed2k.lyoko.io/hash/{}", ed2k_info.hash);
-        eprintln!("通过 HTTP 网关下载: {}", gateway_url);
+        let (gateways, max_retries, max_retry_elapsed) = if let Some(ref config) = self.base.config {
+            let cfg = config.read().await;
+            (cfg.ed2k_gateways.clone(), cfg.max_retries, Duration::from_secs(cfg.max_retry_elapsed_secs))
+        } else {
+            (super::downloader::default_ed2k_gateways(), super::downloader::DEFAULT_MAX_RETRIES, retry::DEFAULT_MAX_RETRY_ELAPSED)
+        };
+        if gateways.is_empty() {
+            return Err("没有配置 ED2K 网关".into());
+        }
+        let gateway_pool = Arc::new(MirrorPool::new(gateways, ED2K_GATEWAY_DROP_THRESHOLD));
 
-        // 用 reqwest 流式下载
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(30))
             .user_agent("Mozilla/5.0 (compatible; TTHSDNext)")
             .build()
             .map_err(|e| format!("HTTP client 创建失败: {}", e))?;
 
-        // 尝试网关请求，失败时返回友好错误
-        let response = client.get(&gateway_url)
-            .send().await
-            .map_err(|e| format!("ED2K 网关请求失败 ({}): {}", gateway_url, e))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            return Err(format!(
-                "ED2K 网关返回错误 HTTP {}: {}\n  hash={}\n  网关={}",
-                status.as_u16(), status.canonical_reason().unwrap_or("Unknown"),
-                ed2k_info.hash, gateway_url
-            ).into());
+        // 续传状态以原始 ed2k:// 链接为 key，与具体选中的网关无关，
+        // 这样某个网关失败切换到下一个网关时已下载的进度不会作废
+        let mut resumable = ResumableFile::open(&task.url, &task.save_path, Some(ed2k_info.size as i64), None, None).await?;
+        let mut hasher = Ed2kHasher::new();
+        // 续传时先把已经落盘的前缀喂给哈希器，之后每次尝试只追加新写入的字节，
+        // 保证最终哈希仍然是整个文件的 MD4 分片哈希
+        if resumable.resume_offset() > 0 {
+            hasher.update(&resumable.read_existing_prefix().await?);
         }
+        let mut downloaded: i64 = resumable.resume_offset();
 
-        let total = response.content_length().unwrap_or(ed2k_info.size) as i64;
-        if let Some(ref monitor) = self.monitor {
-            monitor.set_total_bytes(total);
-        }
+        let mut attempt = 0usize;
+        let started = Instant::now();
 
-        let mut file = tokio::fs::File::create(&task.save_path).await
-            .map_err(|e| format!("创建文件失败: {}", e))?;
+        use futures::StreamExt;
 
-        let mut stream = response.bytes_stream();
-        let mut downloaded: i64 = 0;
+        loop {
+            let base_gateway = gateway_pool.next_mirror().ok_or("所有 ED2K 网关均已被放弃")?;
+            let gateway_url = format!("{}/{}", base_gateway, ed2k_info.hash);
+            eprintln!("通过 HTTP 网关下载: {}", gateway_url);
 
-        use futures::StreamExt;
-        use tokio::io::AsyncWriteExt;
-
-        while let Some(chunk) = stream.next().await {
-            let bytes = chunk.map_err(|e| format!("流读取失败: {}", e))?;
-            file.write_all(&bytes).await
-                .map_err(|e| format!("写入失败: {}", e))?;
-            downloaded += bytes.len() as i64;
-            if let Some(ref monitor) = self.monitor {
-                monitor.add_bytes(bytes.len() as i64).await;
+            let attempt_result: Result<(), Box<dyn std::error::Error + Send + Sync>> = async {
+                let head = client.head(&gateway_url).send().await.ok();
+                let accept_ranges = head.as_ref()
+                    .map(|r| r.headers().get("accept-ranges")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.eq_ignore_ascii_case("bytes"))
+                        .unwrap_or(false))
+                    .unwrap_or(false);
+
+                let mut request = client.get(&gateway_url);
+                if accept_ranges && resumable.resume_offset() > 0 {
+                    request = request.header("Range", format!("bytes={}-", resumable.resume_offset()));
+                }
+
+                let mut response = request.send().await
+                    .map_err(|e| format!("ED2K 网关请求失败 ({}): {}", gateway_url, e))?;
+
+                if resumable.resume_offset() > 0 && response.status().as_u16() != 206 {
+                    resumable.reset().await?;
+                    hasher = Ed2kHasher::new();
+                    response = client.get(&gateway_url).send().await
+                        .map_err(|e| format!("ED2K 网关请求失败 ({}): {}", gateway_url, e))?;
+                }
+
+                let status = response.status();
+                if status.as_u16() >= 400 && status.as_u16() < 500 {
+                    return Err(Box::new(NonRetryableError(format!(
+                        "ED2K 网关返回错误 HTTP {}: {}\n  hash={}\n  网关={}",
+                        status.as_u16(), status.canonical_reason().unwrap_or("Unknown"),
+                        ed2k_info.hash, gateway_url
+                    ))) as Box<dyn std::error::Error + Send + Sync>);
+                }
+                if !status.is_success() {
+                    return Err(format!(
+                        "ED2K 网关返回错误 HTTP {}: {}\n  hash={}\n  网关={}",
+                        status.as_u16(), status.canonical_reason().unwrap_or("Unknown"),
+                        ed2k_info.hash, gateway_url
+                    ).into());
+                }
+
+                downloaded = resumable.resume_offset();
+
+                let mut stream = response.bytes_stream();
+                while let Some(chunk) = stream.next().await {
+                    let bytes = chunk.map_err(|e| format!("流读取失败: {}", e))?;
+                    resumable.write_all(&bytes).await?;
+                    // 边写边算 ED2K 哈希，省去下载完成后再读一遍磁盘
+                    hasher.update(&bytes);
+                    downloaded += bytes.len() as i64;
+                    if let Some(ref monitor) = self.monitor {
+                        monitor.add_bytes(bytes.len() as i64).await;
+                    }
+                }
+                resumable.record_progress();
+                Ok(())
+            }.await;
+
+            match attempt_result {
+                Ok(()) => {
+                    gateway_pool.record_success(&base_gateway);
+                    break;
+                }
+                Err(e) => {
+                    if e.downcast_ref::<NonRetryableError>().is_some() {
+                        return Err(format!("ED2K 下载失败，不可重试的错误: {}", e).into());
+                    }
+
+                    gateway_pool.record_failure(&base_gateway);
+                    if gateway_pool.all_dropped() {
+                        return Err(format!("ED2K 下载失败，所有网关都已被放弃: {}", e).into());
+                    }
+
+                    attempt += 1;
+                    if retry::retry_budget_exhausted(attempt, max_retries, started, max_retry_elapsed) {
+                        return Err(format!(
+                            "ED2K 下载失败，已达到最大重试次数 {} 或耗时预算 {:?}: {}",
+                            max_retries, max_retry_elapsed, e
+                        ).into());
+                    }
+
+                    let jittered = retry::backoff_delay(attempt);
+                    eprintln!("网关 {} 下载失败 ({}), 第 {}/{} 次重试, {:?} 后改用下一个网关",
+                        base_gateway, e, attempt, max_retries, jittered);
+                    tokio::time::sleep(jittered).await;
+                }
             }
         }
 
-        eprintln!("ED2K 下载完成: {:.2} MB ({})",
+        let computed_hash = hasher.finalize_hex(downloaded as u64);
+        if !computed_hash.eq_ignore_ascii_case(&ed2k_info.hash) {
+            let _ = tokio::fs::remove_file(super::resumable_download::part_path(&task.save_path)).await;
+            ResumeState::delete(&task.save_path);
+            return Err(format!(
+                "ED2K 哈希校验失败: 期望 {}，实际 {}（网关可能返回了错误的文件）",
+                ed2k_info.hash, computed_hash
+            ).into());
+        }
+
+        resumable.finish().await?;
+
+        eprintln!("ED2K 下载完成并通过哈希校验: {:.2} MB ({})",
             downloaded as f64 / 1024.0 / 1024.0, ed2k_info.name);
         Ok(())
     }
@@ -165,8 +331,12 @@ impl Downloader for ED2KDownloader {
         "ED2K".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
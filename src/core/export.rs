@@ -1,28 +1,177 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
-use super::downloader::{HSDownloader, DownloadTask, DownloadConfig, Event, EventType, UA};
+use super::downloader::{DownloaderCommand, HSDownloader, DownloadTask, DownloadConfig, Event, EventType, UA, DEFAULT_MAX_RETRIES, DEFAULT_MAX_CONCURRENT_TASKS};
 use super::send_message::send_message;
 
 lazy_static::lazy_static! {
-    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+    pub(crate) static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
 }
 
-fn get_downloaders() -> &'static Mutex<HashMap<i32, Arc<RwLock<HSDownloader>>>> {
+pub(crate) fn get_downloaders() -> &'static Mutex<HashMap<i32, Arc<RwLock<HSDownloader>>>> {
     static DOWNLOADERS: once_cell::sync::Lazy<Mutex<HashMap<i32, Arc<RwLock<HSDownloader>>>>> =
         once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
     &DOWNLOADERS
 }
 
-fn get_downloader_id() -> &'static Mutex<i32> {
+pub(crate) fn get_downloader_id() -> &'static Mutex<i32> {
     static DOWNLOADER_ID: once_cell::sync::Lazy<Mutex<i32>> =
         once_cell::sync::Lazy::new(|| Mutex::new(0));
     &DOWNLOADER_ID
 }
 
+/// 把新建的下载器注册到全局表里并分配 id，同时启动它的后台命令循环，
+/// 这样 `pause_download`/`resume_download`/`stop_download` 只需要投递命令就能立刻返回
+pub(crate) fn register_downloader(downloader: HSDownloader) -> (i32, Arc<RwLock<HSDownloader>>) {
+    let downloader = Arc::new(RwLock::new(downloader));
+
+    let downloader_id = {
+        let mut id = get_downloader_id().lock().unwrap();
+        *id += 1;
+        *id
+    };
+
+    {
+        let mut downloaders = get_downloaders().lock().unwrap();
+        downloaders.insert(downloader_id, downloader.clone());
+    }
+
+    spawn_command_loop(downloader.clone(), downloader_id);
+
+    (downloader_id, downloader)
+}
+
+/// 消费 `command_tx` 投递的控制指令，在后台调用真正的暂停/恢复/停止逻辑，
+/// 结果通过 `send_event` 广播，不回传给发起命令的调用方
+pub(crate) fn spawn_command_loop(downloader: Arc<RwLock<HSDownloader>>, id: i32) {
+    RUNTIME.spawn(async move {
+        let mut rx = match downloader.read().await.take_command_receiver().await {
+            Some(rx) => rx,
+            None => return,
+        };
+
+        while let Some(command) = rx.recv().await {
+            match command {
+                DownloaderCommand::Pause => {
+                    downloader.read().await.pause_download().await;
+                    persist_if_configured(&downloader, id).await;
+                }
+                DownloaderCommand::Resume => {
+                    if let Err(e) = downloader.read().await.resume_download().await {
+                        eprintln!("恢复下载器 {} 失败: {:?}", id, e);
+                    }
+                    persist_if_configured(&downloader, id).await;
+                }
+                DownloaderCommand::Stop => {
+                    let _ = downloader.read().await.stop_download().await;
+                    prune_if_configured(&downloader, id).await;
+                    get_downloaders().lock().unwrap().remove(&id);
+                    break;
+                }
+                DownloaderCommand::SetThreadCount(count) => {
+                    let config = downloader.read().await.config.clone();
+                    config.write().await.thread_count = count;
+                    downloader.read().await.apply_thread_count(count).await;
+                    persist_if_configured(&downloader, id).await;
+                }
+                DownloaderCommand::Shutdown => break,
+            }
+        }
+    });
+}
+
+/// 如果下载器配置了 `db_path`，把它当前的配置写入持久化目录
+async fn persist_if_configured(downloader: &Arc<RwLock<HSDownloader>>, id: i32) {
+    let config = downloader.read().await.config.clone();
+    let cfg = config.read().await;
+    if let Some(ref db_path) = cfg.db_path {
+        let entry = super::persistence::PersistedDownloader {
+            id,
+            config: super::persistence::PersistedConfig::from(&*cfg),
+            chunk_bitmap: Vec::new(),
+        };
+        if let Err(e) = super::persistence::save(db_path, &entry) {
+            eprintln!("持久化下载器 {} 失败: {:?}", id, e);
+        }
+    }
+}
+
+/// 下载器完成/停止后，如果配置了 `db_path`，从持久化目录移除它的记录
+async fn prune_if_configured(downloader: &Arc<RwLock<HSDownloader>>, id: i32) {
+    let config = downloader.read().await.config.clone();
+    let cfg = config.read().await;
+    if let Some(ref db_path) = cfg.db_path {
+        if let Err(e) = super::persistence::prune(db_path, id) {
+            eprintln!("清理持久化记录 {} 失败: {:?}", id, e);
+        }
+    }
+}
+
+/// 重启后从 `db_path` 恢复之前注册过的下载器
+///
+/// 重新把每条记录塞回全局 `HashMap`，并把 `DOWNLOADER_ID` 推进到不小于
+/// 已恢复的最大 id，避免和新建下载器冲突。返回值是恢复出的 id 列表（JSON 数组），
+/// 恢复只是重新登记下载器，不会自动开始传输；要继续未完成的任务，调用方需要对这些 id
+/// 调用 `start_download_id`（多任务用 `start_multiple_downloads_id`）。`resume_download`
+/// 只把状态标回 Running 并广播事件，不会发起传输。
+#[unsafe(no_mangle)]
+pub extern "C" fn restore_downloads(db_path: *const i8) -> *mut i8 {
+    if db_path.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let path_str = unsafe { std::ffi::CStr::from_ptr(db_path as *const u8 as *const std::ffi::c_char) };
+    let path = match path_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    let entries = super::persistence::load_all(path);
+    let mut restored_ids = Vec::new();
+    let mut restored_downloaders = Vec::new();
+
+    {
+        let mut id_guard = get_downloader_id().lock().unwrap();
+        let mut downloaders = get_downloaders().lock().unwrap();
+
+        for entry in entries {
+            let config = entry.config.into_download_config();
+            let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
+            downloaders.insert(entry.id, downloader.clone());
+            restored_downloaders.push((entry.id, downloader));
+            restored_ids.push(entry.id);
+
+            if entry.id > *id_guard {
+                *id_guard = entry.id;
+            }
+        }
+    }
+
+    for (id, downloader) in restored_downloaders {
+        spawn_command_loop(downloader, id);
+    }
+
+    let json = serde_json::to_string(&restored_ids).unwrap_or_else(|_| "[]".to_string());
+    match std::ffi::CString::new(json) {
+        Ok(c_string) => c_string.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 释放 `restore_downloads` 返回的字符串
+#[unsafe(no_mangle)]
+pub extern "C" fn free_restore_result(ptr: *mut i8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        drop(std::ffi::CString::from_raw(ptr));
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn start_download(
     tasks_data: *const i8,
@@ -35,6 +184,7 @@ pub extern "C" fn start_download(
     remote_callback_url: *const i8,
     use_socket: *const bool,
     is_multiple: *const bool,
+    max_speed_bps: i64,
 ) -> i32 {
     if tasks_data.is_null() || task_count <= 0 {
         eprintln!("无效参数: tasks_data={:?}, task_count={}", tasks_data, task_count);
@@ -98,23 +248,27 @@ pub extern "C" fn start_download(
         use_socket: use_socket_val,
         show_name: String::new(),
         user_agent: UA.to_string(),
+        extract: None,
+        db_path: None,
+        max_speed_bps: if max_speed_bps > 0 { Some(max_speed_bps as u64) } else { None },
+        max_retries: DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: None,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
     };
 
-    let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
-
-    let downloader_id = {
-        let mut id = get_downloader_id().lock().unwrap();
-        *id += 1;
-        *id
-    };
-
-    {
-        let mut downloaders = get_downloaders().lock().unwrap();
-        downloaders.insert(downloader_id, downloader.clone());
-    }
+    let (downloader_id, downloader) = register_downloader(HSDownloader::new(config));
 
     let downloader_clone = downloader.clone();
     RUNTIME.spawn(async move {
+        persist_if_configured(&downloader_clone, downloader_id).await;
+
         let result = if is_multiple_val {
             downloader_clone.read().await.start_multiple_downloads().await
         } else {
@@ -139,8 +293,13 @@ pub extern "C" fn start_download(
             let _ = send_message(event, data, &config, &ws_client, &socket_client).await;
         }
 
+        prune_if_configured(&downloader_clone, downloader_id).await;
+
         let mut downloaders = get_downloaders().lock().unwrap();
         downloaders.remove(&downloader_id);
+        drop(downloaders);
+
+        let _ = downloader_clone.read().await.command_tx.send(DownloaderCommand::Shutdown);
     });
 
     downloader_id
@@ -157,6 +316,7 @@ pub extern "C" fn get_downloader(
     _user_agent: *const i8,
     remote_callback_url: *const i8,
     use_socket: *const bool,
+    max_speed_bps: i64,
 ) -> i32 {
     if tasks_data.is_null() || task_count <= 0 {
         return -1;
@@ -207,20 +367,162 @@ pub extern "C" fn get_downloader(
         use_socket: use_socket_val,
         show_name: String::new(),
         user_agent: UA.to_string(),
+        extract: None,
+        db_path: None,
+        max_speed_bps: if max_speed_bps > 0 { Some(max_speed_bps as u64) } else { None },
+        max_retries: DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: None,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
     };
 
-    let downloader = Arc::new(RwLock::new(HSDownloader::new(config)));
+    let (downloader_id, _downloader) = register_downloader(HSDownloader::new(config));
 
-    let downloader_id = {
-        let mut id = get_downloader_id().lock().unwrap();
-        *id += 1;
-        *id
+    downloader_id
+}
+
+/// 启动下载任务，边下载边按 `extract_format` 流式解压（单连接顺序读取，不分块）
+///
+/// `extract_format` 接受 "tar.gz" / "tar.bz2" / "tar.lz4"，传入其它值或空指针
+/// 则等同于 `start_download`（不解压）。解压目标目录固定为任务 `save_path`
+/// 所在目录，且只对单任务（非 `is_multiple`）生效。
+#[unsafe(no_mangle)]
+pub extern "C" fn start_download_extract(
+    tasks_data: *const i8,
+    task_count: i32,
+    thread_count: i32,
+    chunk_size_mb: i32,
+    callback: usize,
+    use_callback_url: bool,
+    _user_agent: *const i8,
+    remote_callback_url: *const i8,
+    use_socket: *const bool,
+    extract_format: *const i8,
+    max_speed_bps: i64,
+) -> i32 {
+    if tasks_data.is_null() || task_count <= 0 {
+        eprintln!("无效参数: tasks_data={:?}, task_count={}", tasks_data, task_count);
+        return -1;
+    }
+
+    let tasks_str = unsafe { std::ffi::CStr::from_ptr(tasks_data as *const u8 as *const std::ffi::c_char) };
+    let tasks_json = match tasks_str.to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("转换任务数据失败: {:?}", e);
+            return -1;
+        }
     };
 
-    {
+    let tasks: Vec<DownloadTask> = match serde_json::from_str(tasks_json) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("解析任务数据失败: {:?}", e);
+            return -1;
+        }
+    };
+
+    let callback_url = if !remote_callback_url.is_null() {
+        let url_str = unsafe { std::ffi::CStr::from_ptr(remote_callback_url as *const u8 as *const std::ffi::c_char) };
+        match url_str.to_str() {
+            Ok(s) if !s.is_empty() => Some(s.to_string()),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let use_socket_val = if !use_socket.is_null() {
+        Some(unsafe { *use_socket })
+    } else {
+        None
+    };
+
+    let extract = if !extract_format.is_null() {
+        let fmt_str = unsafe { std::ffi::CStr::from_ptr(extract_format as *const u8 as *const std::ffi::c_char) };
+        match fmt_str.to_str().unwrap_or("") {
+            "tar.gz" => Some(super::archive_extractor::ArchiveFormat::TarGz),
+            "tar.bz2" => Some(super::archive_extractor::ArchiveFormat::TarBz2),
+            "tar.lz4" => Some(super::archive_extractor::ArchiveFormat::TarLz4),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let callback_func = if callback != 0 {
+        unsafe {
+            Some(std::mem::transmute::<usize, super::downloader::ProgressCallback>(callback))
+        }
+    } else {
+        None
+    };
+
+    let config = DownloadConfig {
+        tasks,
+        thread_count: thread_count as usize,
+        chunk_size_mb: chunk_size_mb as usize,
+        callback_func,
+        use_callback_url,
+        callback_url,
+        use_socket: use_socket_val,
+        show_name: String::new(),
+        user_agent: UA.to_string(),
+        extract,
+        db_path: None,
+        max_speed_bps: if max_speed_bps > 0 { Some(max_speed_bps as u64) } else { None },
+        max_retries: DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: None,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
+    };
+
+    let (downloader_id, downloader) = register_downloader(HSDownloader::new(config));
+
+    let downloader_clone = downloader.clone();
+    RUNTIME.spawn(async move {
+        persist_if_configured(&downloader_clone, downloader_id).await;
+
+        let result = downloader_clone.read().await.start_download().await;
+
+        if let Err(e) = result {
+            let event = Event {
+                event_type: EventType::Err,
+                name: "错误".to_string(),
+                show_name: String::new(),
+                id: String::new(),
+            };
+
+            let mut data = HashMap::new();
+            data.insert("Error".to_string(), serde_json::Value::String(e.to_string()));
+
+            let config = downloader_clone.read().await.config.clone();
+            let ws_client = downloader_clone.read().await.ws_client.clone();
+            let socket_client = downloader_clone.read().await.socket_client.clone();
+
+            let _ = send_message(event, data, &config, &ws_client, &socket_client).await;
+        }
+
+        prune_if_configured(&downloader_clone, downloader_id).await;
+
         let mut downloaders = get_downloaders().lock().unwrap();
-        downloaders.insert(downloader_id, downloader);
-    }
+        downloaders.remove(&downloader_id);
+        drop(downloaders);
+
+        let _ = downloader_clone.read().await.command_tx.send(DownloaderCommand::Shutdown);
+    });
 
     downloader_id
 }
@@ -257,6 +559,9 @@ pub extern "C" fn start_download_id(id: i32) -> i32 {
 
                 let mut downloaders = get_downloaders().lock().unwrap();
                 downloaders.remove(&id);
+                drop(downloaders);
+
+                let _ = d_clone.read().await.command_tx.send(DownloaderCommand::Shutdown);
             });
             0
         }
@@ -296,6 +601,9 @@ pub extern "C" fn start_multiple_downloads_id(id: i32) -> i32 {
 
                 let mut downloaders = get_downloaders().lock().unwrap();
                 downloaders.remove(&id);
+                drop(downloaders);
+
+                let _ = d_clone.read().await.command_tx.send(DownloaderCommand::Shutdown);
             });
             0
         }
@@ -303,6 +611,42 @@ pub extern "C" fn start_multiple_downloads_id(id: i32) -> i32 {
     }
 }
 
+/// 拉取式查询下载器状态，返回堆分配的 JSON C 字符串 (`StatusSnapshot`)。
+/// 调用方用完后必须调用 `free_status_string` 释放，避免每次轮询都泄漏内存。
+#[unsafe(no_mangle)]
+pub extern "C" fn get_download_status(id: i32) -> *mut i8 {
+    let downloaders = get_downloaders().lock().unwrap();
+    let downloader = downloaders.get(&id).cloned();
+    drop(downloaders);
+
+    let downloader = match downloader {
+        Some(d) => d,
+        None => return std::ptr::null_mut(),
+    };
+
+    let snapshot = RUNTIME.block_on(async {
+        downloader.read().await.status.snapshot().await
+    });
+
+    let json = serde_json::to_string(&snapshot).unwrap_or_else(|_| "{}".to_string());
+    match std::ffi::CString::new(json) {
+        Ok(c_string) => c_string.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 释放 `get_download_status` 返回的字符串
+#[unsafe(no_mangle)]
+pub extern "C" fn free_status_string(ptr: *mut i8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        drop(std::ffi::CString::from_raw(ptr));
+    }
+}
+
+/// 投递 `Pause` 命令，立即返回，不等待后台命令循环真正执行暂停
 #[unsafe(no_mangle)]
 pub extern "C" fn pause_download(id: i32) -> i32 {
     let downloaders = get_downloaders().lock().unwrap();
@@ -310,16 +654,18 @@ pub extern "C" fn pause_download(id: i32) -> i32 {
     drop(downloaders);
 
     match downloader {
-        Some(d) => {
-            RUNTIME.block_on(async {
-                d.read().await.pause_download().await;
-            });
-            0
-        }
+        Some(d) => match d.try_read() {
+            Ok(d) => match d.command_tx.send(DownloaderCommand::Pause) {
+                Ok(_) => 0,
+                Err(_) => -1,
+            },
+            Err(_) => -1,
+        },
         None => -1,
     }
 }
 
+/// 投递 `Resume` 命令，立即返回；恢复是否成功通过事件通道广播，而非返回值
 #[unsafe(no_mangle)]
 pub extern "C" fn resume_download(id: i32) -> i32 {
     let downloaders = get_downloaders().lock().unwrap();
@@ -327,35 +673,58 @@ pub extern "C" fn resume_download(id: i32) -> i32 {
     drop(downloaders);
 
     match downloader {
-        Some(d) => {
-            let result = RUNTIME.block_on(async {
-                d.read().await.resume_download().await
-            });
-            match result {
+        Some(d) => match d.try_read() {
+            Ok(d) => match d.command_tx.send(DownloaderCommand::Resume) {
                 Ok(_) => 0,
                 Err(_) => -1,
-            }
-        }
+            },
+            Err(_) => -1,
+        },
         None => -1,
     }
 }
 
+/// 投递 `Stop` 命令，立即返回；下载器会在命令循环里处理完停止逻辑后自行从全局表移除
 #[unsafe(no_mangle)]
 pub extern "C" fn stop_download(id: i32) -> i32 {
-    let mut downloaders = get_downloaders().lock().unwrap();
-    let downloader = downloaders.remove(&id);
+    let downloaders = get_downloaders().lock().unwrap();
+    let downloader = downloaders.get(&id).cloned();
     drop(downloaders);
 
     match downloader {
-        Some(d) => {
-            let result = RUNTIME.block_on(async {
-                d.read().await.stop_download().await
-            });
-            match result {
+        Some(d) => match d.try_read() {
+            Ok(d) => match d.command_tx.send(DownloaderCommand::Stop) {
                 Ok(_) => 0,
                 Err(_) => -1,
-            }
-        }
+            },
+            Err(_) => -1,
+        },
         None => -1,
     }
-}
\ No newline at end of file
+}
+
+/// 启动一个 IPC 守护监听器，通过 Unix 域套接字接收长度前缀的 JSON 命令帧，
+/// 和进程内 FFI 调用共用同一张下载器表。监听器运行在共享的 `RUNTIME` 上，
+/// 本函数本身立即返回，不会阻塞调用方。
+///
+/// 目前仅支持 Unix 域套接字，Windows 命名管道尚未实现。
+#[unsafe(no_mangle)]
+pub extern "C" fn run_ipc_server(socket_path: *const i8) -> i32 {
+    if socket_path.is_null() {
+        return -1;
+    }
+
+    let path_str = unsafe { std::ffi::CStr::from_ptr(socket_path as *const u8 as *const std::ffi::c_char) };
+    let path = match path_str.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return -1,
+    };
+
+    RUNTIME.spawn(async move {
+        if let Err(e) = super::ipc_server::run(path).await {
+            eprintln!("IPC 服务器异常退出: {:?}", e);
+        }
+    });
+
+    0
+}
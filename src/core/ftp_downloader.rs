@@ -1,11 +1,25 @@
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use tokio::sync::RwLock;
 
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
 use super::performance_monitor::PerformanceMonitor;
+use super::resumable_download::{ResumeState, part_path};
+use super::connection_pool::ConnPool;
+
+/// TLS 握手没有自己的超时旋钮时使用的默认等待时间
+pub const DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS: u64 = 15;
+
+/// `suppaftp` 的 rustls feature 期望的连接器类型
+type RustlsConnector = std::sync::Arc<rustls::ClientConfig>;
+
+/// 进程级别的 FTP 连接池，按 (host, port, username) 分桶；同一目标的重复下载
+/// 可以跳过三次握手 + 登录，直接复用上一次留下的会话
+static FTP_POOL: once_cell::sync::Lazy<ConnPool<(String, u16, String), suppaftp::FtpStream>> =
+    once_cell::sync::Lazy::new(ConnPool::new);
 
 /// FTP 下载器
 /// 使用 suppaftp 的同步 API + tokio::task::spawn_blocking
@@ -15,6 +29,140 @@ pub struct FTPDownloader {
     monitor: Option<Arc<PerformanceMonitor>>,
 }
 
+/// 用系统/webpki 根证书构建一个 rustls 连接器，FTPS 显式/隐式握手共用
+fn build_tls_connector() -> Result<RustlsConnector, String> {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    Ok(std::sync::Arc::new(config))
+}
+
+/// 在独立线程里跑一段可能阻塞的同步操作，超过 `timeout` 还没返回就放弃等待并报错，
+/// 而不是让调用方（这里是 `spawn_blocking` 线程）被一个不回应的 TLS 握手永久卡死；
+/// 专门给 suppaftp 自己没有握手超时参数的阶段用，底层线程超时后会自然泄漏
+fn run_with_timeout<T, F>(op_name: &str, timeout: Duration, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(format!("{}超时 (>{}s)", op_name, timeout.as_secs())),
+    }
+}
+
+/// `suppaftp::list` 返回的一条原始 UNIX `ls -l` 风格行里解析出来的条目信息；
+/// 只取得下目录递归下载用得上的字段（名字、大小、是不是目录/符号链接），不追求
+/// 覆盖所有 FTP 服务端可能输出的怪异格式（比如 DOS 风格 LIST，那种服务器走不到这条路径）
+struct FtpEntry {
+    name: String,
+    size: i64,
+    is_dir: bool,
+    is_symlink: bool,
+}
+
+/// 解析形如 `drwxr-xr-x 2 user group 4096 Jan 01 00:00 dirname` 的一行；
+/// 权限字段第一个字符区分目录 (`d`) / 符号链接 (`l`) / 普通文件 (`-`)，
+/// 符号链接的 `name -> target` 只取箭头前的部分
+fn parse_unix_list_line(line: &str) -> Option<FtpEntry> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 9 {
+        return None;
+    }
+
+    let perms = tokens[0];
+    let size: i64 = tokens[4].parse().ok()?;
+    let mut name = tokens[8..].join(" ");
+
+    let is_symlink = perms.starts_with('l');
+    if is_symlink {
+        if let Some(pos) = name.find(" -> ") {
+            name.truncate(pos);
+        }
+    }
+
+    if name == "." || name == ".." {
+        return None;
+    }
+
+    Some(FtpEntry { name, size, is_dir: perms.starts_with('d'), is_symlink })
+}
+
+/// 判断远程路径是文件还是目录：`SIZE` 命令对目录一律报错，成功就肯定是文件；
+/// 失败了再尝试 `CWD` 进去探测，能进去就是目录，探测完记得切回原来的工作目录
+fn is_ftp_directory(ftp: &mut suppaftp::FtpStream, path: &str) -> Result<bool, String> {
+    if ftp.size(path).is_ok() {
+        return Ok(false);
+    }
+
+    let original_dir = ftp.pwd().map_err(|e| format!("获取当前目录失败: {}", e))?;
+    let is_dir = ftp.cwd(path).is_ok();
+    if is_dir {
+        ftp.cwd(&original_dir).map_err(|e| format!("恢复工作目录失败: {}", e))?;
+    }
+
+    Ok(is_dir)
+}
+
+/// 递归遍历远程目录，在本地镜像出同样的目录结构，返回需要下载的
+/// (远程路径, 本地路径, 大小) 列表；符号链接默认跳过，`include`/`exclude`
+/// glob 按文件相对根目录的路径过滤
+fn walk_ftp_directory(
+    ftp: &mut suppaftp::FtpStream,
+    remote_root: &str,
+    local_root: &Path,
+    include: &Option<String>,
+    exclude: &Option<String>,
+    follow_symlinks: bool,
+) -> Result<Vec<(String, PathBuf, i64)>, String> {
+    let mut files = Vec::new();
+    let mut stack = vec![(remote_root.to_string(), local_root.to_path_buf(), String::new())];
+
+    while let Some((remote_dir, local_dir, rel_prefix)) = stack.pop() {
+        std::fs::create_dir_all(&local_dir)
+            .map_err(|e| format!("创建目录失败 {}: {}", local_dir.display(), e))?;
+
+        let lines = ftp.list(Some(&remote_dir))
+            .map_err(|e| format!("列出目录失败 {}: {}", remote_dir, e))?;
+
+        for line in lines {
+            let entry = match parse_unix_list_line(&line) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            if entry.is_symlink && !follow_symlinks {
+                continue;
+            }
+
+            let remote_child = format!("{}/{}", remote_dir.trim_end_matches('/'), entry.name);
+            let local_child = local_dir.join(&entry.name);
+            let rel_child = if rel_prefix.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", rel_prefix, entry.name)
+            };
+
+            if entry.is_dir {
+                stack.push((remote_child, local_child, rel_child));
+            } else if super::glob_match::should_download(&rel_child, include, exclude) {
+                files.push((remote_child, local_child, entry.size));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 impl FTPDownloader {
     pub async fn new(config: Arc<RwLock<DownloadConfig>>) -> Self {
         let monitor = super::performance_monitor::get_global_monitor().await;
@@ -22,18 +170,24 @@ impl FTPDownloader {
         FTPDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
         }
     }
 
-    /// 解析 FTP URL 为 (host:port, path, username, password)
-    fn parse_ftp_url(url: &str) -> Result<(String, String, String, String), Box<dyn std::error::Error + Send + Sync>> {
+    /// 解析 FTP URL 为 (host:port, host, port, path, username, password, URL 是否为 ftps:// scheme)
+    fn parse_ftp_url(url: &str) -> Result<(String, String, u16, String, String, String, bool), Box<dyn std::error::Error + Send + Sync>> {
         let parsed = url::Url::parse(url)
             .map_err(|e| format!("无效的 FTP URL: {}", e))?;
 
+        let scheme_secure = match parsed.scheme() {
+            "ftp" => false,
+            "ftps" => true,
+            other => return Err(format!("不支持的 FTP URL scheme: {}", other).into()),
+        };
+
         let host = parsed.host_str()
             .ok_or("FTP URL 缺少主机名")?
             .to_string();
@@ -46,53 +200,232 @@ impl FTPDownloader {
         };
         let password = parsed.password().unwrap_or("anonymous@").to_string();
 
-        Ok((format!("{}:{}", host, port), path, username, password))
+        Ok((format!("{}:{}", host, port), host, port, path, username, password, scheme_secure))
     }
 }
 
 #[async_trait::async_trait]
 impl Downloader for FTPDownloader {
     async fn download(&mut self, task: &DownloadTask) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let (addr, path, username, password) = Self::parse_ftp_url(&task.url)?;
+        let (addr, host, port, path, username, password, scheme_secure) = Self::parse_ftp_url(&task.url)?;
+        let url = task.url.clone();
         let save_path = task.save_path.clone();
         let monitor = self.monitor.clone();
+        let secure = scheme_secure || task.ftp_secure;
+        let implicit_tls = task.ftp_implicit_tls;
+        let handshake_timeout = Duration::from_secs(
+            task.ftp_tls_timeout_secs.unwrap_or(DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS)
+        );
+        let dir_include_glob = task.dir_include_glob.clone();
+        let dir_exclude_glob = task.dir_exclude_glob.clone();
+        let dir_follow_symlinks = task.dir_follow_symlinks;
+
+        let (pool_max_size, pool_idle_timeout) = if let Some(ref config) = self.base.config {
+            let cfg = config.read().await;
+            (cfg.conn_pool_max_size, Duration::from_secs(cfg.conn_pool_idle_timeout_secs))
+        } else {
+            (super::connection_pool::DEFAULT_CONN_POOL_MAX_SIZE, Duration::from_secs(super::connection_pool::DEFAULT_CONN_POOL_IDLE_TIMEOUT_SECS))
+        };
+        let pool_key = (host.clone(), port, username.clone());
+        let pooled_conn = FTP_POOL.acquire(&pool_key, pool_idle_timeout).await;
+        let running = self.base.running.clone();
+        let rt_handle = tokio::runtime::Handle::current();
 
-        eprintln!("FTP 连接: {} (用户: {})", addr, username);
+        eprintln!("FTP 连接: {} (用户: {}, TLS: {}, 复用连接: {})", addr, username, secure, pooled_conn.is_some());
 
         // 在阻塞线程中执行同步 FTP 操作
-        let result = tokio::task::spawn_blocking(move || -> Result<(i64, f64), String> {
+        let result = tokio::task::spawn_blocking(move || -> Result<(i64, f64, Option<suppaftp::FtpStream>), String> {
             use suppaftp::FtpStream;
 
-            // 建立连接
-            let mut ftp = FtpStream::connect(&addr)
-                .map_err(|e| format!("FTP 连接失败: {}", e))?;
-
-            // 登录
-            ftp.login(&username, &password)
-                .map_err(|e| format!("FTP 登录失败: {}", e))?;
+            // 优先复用池子里还活着的连接：先发一次 NOOP 探活，NOOP 失败说明连接已经
+            // 断了（服务端超时踢掉、网络中断等），退回去走一遍完整的新建连接流程
+            let mut ftp = match pooled_conn.and_then(|mut ftp| {
+                if ftp.noop().is_ok() { Some(ftp) } else { None }
+            }) {
+                Some(ftp) => ftp,
+                None => {
+                    // 建立连接：隐式 TLS 从第一个字节就加密，没有明文协商阶段，
+                    // 必须用单独的连接入口；显式 TLS 先明文连接，登录前再用 AUTH TLS 升级
+                    let mut ftp = if secure && implicit_tls {
+                        let connector = build_tls_connector()?;
+                        let domain = host.clone();
+                        run_with_timeout("FTPS 隐式 TLS 握手", handshake_timeout, {
+                            let addr = addr.clone();
+                            move || {
+                                FtpStream::connect_secure_implicit(&addr, connector, &domain)
+                                    .map_err(|e| format!("FTPS 隐式 TLS 握手失败: {}", e))
+                            }
+                        })?
+                    } else {
+                        let plain = FtpStream::connect(&addr)
+                            .map_err(|e| format!("FTP 连接失败: {}", e))?;
+
+                        if secure {
+                            let connector = build_tls_connector()?;
+                            let domain = host.clone();
+                            run_with_timeout("FTPS 显式 TLS 握手", handshake_timeout, move || {
+                                plain.into_secure(connector, &domain)
+                                    .map_err(|e| format!("FTPS 显式 TLS 握手失败: {}", e))
+                            })?
+                        } else {
+                            plain
+                        }
+                    };
+
+                    // 登录：控制通道和数据通道都已经在加密连接上进行，凭据不会明文传输
+                    ftp.login(&username, &password)
+                        .map_err(|e| format!("FTP 登录失败: {}", e))?;
+
+                    ftp
+                }
+            };
 
-            // 设置二进制传输模式
+            // 设置二进制传输模式：连接是新建的还是从池子里复用的都要发一遍，
+            // 复用的连接理论上早就是 Binary 模式，但这条命令很轻量，重发一遍换来确定性更划算
             ftp.transfer_type(suppaftp::types::FileType::Binary)
                 .map_err(|e| format!("设置二进制模式失败: {}", e))?;
 
-            // 获取文件大小
+            // 远程路径是目录时走递归下载：先遍历整棵树算出总大小喂给 PerformanceMonitor，
+            // 再逐个文件下载；不支持续传，每次都是全新下载整棵树（或 include/exclude 过滤后的子集）
+            if is_ftp_directory(&mut ftp, &path)? {
+                eprintln!("FTP 远程路径是目录，进入递归下载: {}", path);
+
+                let local_root = PathBuf::from(&save_path);
+                let files = walk_ftp_directory(&mut ftp, &path, &local_root, &dir_include_glob, &dir_exclude_glob, dir_follow_symlinks)?;
+                let total_size: i64 = files.iter().map(|(_, _, size)| *size).sum();
+                eprintln!("FTP 目录下载: {} 个文件，共 {} bytes", files.len(), total_size);
+
+                if let Some(ref monitor) = monitor {
+                    monitor.set_total_bytes(total_size);
+                }
+
+                let start_time = Instant::now();
+                let mut downloaded_total: i64 = 0;
+
+                for (remote_file, local_file, _size) in &files {
+                    if !running.load(std::sync::atomic::Ordering::Relaxed) {
+                        return Err("FTP 目录下载已取消".to_string());
+                    }
+
+                    if let Some(parent) = local_file.parent() {
+                        std::fs::create_dir_all(parent)
+                            .map_err(|e| format!("创建目录失败: {}", e))?;
+                    }
+
+                    let mut out = std::fs::File::create(local_file)
+                        .map_err(|e| format!("创建文件失败 {}: {}", local_file.display(), e))?;
+
+                    let n: i64 = ftp.retr(remote_file, |reader| {
+                        let mut buf = vec![0u8; 64 * 1024];
+                        let mut total: i64 = 0;
+
+                        loop {
+                            if !running.load(std::sync::atomic::Ordering::Relaxed) {
+                                return Err(suppaftp::FtpError::ConnectionError(
+                                    std::io::Error::new(std::io::ErrorKind::Other, "下载已取消")
+                                ));
+                            }
+
+                            let n = reader.read(&mut buf)
+                                .map_err(|e| suppaftp::FtpError::ConnectionError(e))?;
+                            if n == 0 {
+                                break;
+                            }
+
+                            out.write_all(&buf[..n])
+                                .map_err(|e| suppaftp::FtpError::ConnectionError(e))?;
+
+                            total += n as i64;
+                            if let Some(ref monitor) = monitor {
+                                rt_handle.block_on(monitor.add_bytes(n as i64));
+                            }
+                        }
+
+                        Ok(total)
+                    }).map_err(|e| format!("下载文件失败 {}: {}", remote_file, e))?;
+
+                    downloaded_total += n;
+                }
+
+                let elapsed = start_time.elapsed().as_secs_f64();
+                eprintln!("FTP 目录下载完成: {} 个文件, {:.2} MB, 用时 {:.1}s",
+                    files.len(), downloaded_total as f64 / 1024.0 / 1024.0, elapsed);
+
+                return Ok((downloaded_total, elapsed, Some(ftp)));
+            }
+
+            // 获取文件大小和修改时间，后者用于判断续传状态是否仍然对应同一份远端文件
             let file_size = ftp.size(&path)
                 .map_err(|e| format!("获取文件大小失败: {}", e))? as i64;
+            let mtime = ftp.mdtm(&path).ok().map(|t| t.to_string());
 
             eprintln!("FTP 文件大小: {} bytes ({:.2} MB)",
                 file_size, file_size as f64 / 1024.0 / 1024.0);
 
-            // 创建输出文件
-            let mut file = std::fs::File::create(&save_path)
-                .map_err(|e| format!("创建文件失败: {}", e))?;
+            // 续传判断: 复用 ED2K/HTTP3/Metalink 共用的 `.part` + 续传状态机制；
+            // 只有 url、大小、mtime 都和上次记录一致，且 `.part` 文件实际长度和记录的
+            // 已下载字节数吻合，才认为可以安全续传，否则一律从零开始重新下载
+            let part = part_path(&save_path);
+            let existing = ResumeState::load(&save_path)
+                .filter(|s| s.matches(&url, Some(file_size), &None, &mtime));
+            let part_len = std::fs::metadata(&part).ok().map(|m| m.len() as i64);
+
+            let resume_offset = match (&existing, part_len) {
+                (Some(state), Some(len)) if len == state.downloaded && state.downloaded > 0 && state.downloaded < file_size => {
+                    eprintln!("FTP 发现可续传进度: {} 已下载 {} bytes", save_path, state.downloaded);
+                    state.downloaded
+                }
+                _ => 0,
+            };
+
+            let mut file = if resume_offset > 0 {
+                // REST: 告诉服务端下一次 retr 从这个偏移开始发送数据
+                ftp.resume_transfer(resume_offset as usize)
+                    .map_err(|e| format!("设置 FTP 续传偏移失败: {}", e))?;
+                std::fs::OpenOptions::new().append(true).open(&part)
+                    .map_err(|e| format!("打开续传文件失败: {}", e))?
+            } else {
+                std::fs::File::create(&part)
+                    .map_err(|e| format!("创建文件失败: {}", e))?
+            };
+
+            let mut state = ResumeState {
+                url: url.clone(),
+                total_size: Some(file_size),
+                downloaded: resume_offset,
+                etag: None,
+                last_modified: mtime.clone(),
+            };
+            state.save(&save_path).map_err(|e| format!("写入续传状态失败: {}", e))?;
+
+            // 进度监控：总大小在这里就能定下来，续传已经落盘的前缀字节数也一次性计入，
+            // 剩下的新字节在下面的 retr 循环里逐块上报，这样速度/进度是实时的而不是
+            // 下载完才从 0 跳到 100%
+            if let Some(ref monitor) = monitor {
+                monitor.set_total_bytes(file_size);
+                if resume_offset > 0 {
+                    rt_handle.block_on(monitor.add_bytes(resume_offset));
+                }
+            }
 
-            // 使用 retr 回调进行流式下载
+            // 使用 retr 回调进行流式下载；`bytes_so_far` 记录回调内部已经写盘的
+            // 字节数，取消发生时这个计数已经落后于最新一次 `write_all` 最多一轮，
+            // 供回调返回取消错误后把真实的已下载字节数写回续传状态
+            let bytes_so_far = std::cell::Cell::new(0i64);
             let start_time = Instant::now();
-            let downloaded: i64 = ftp.retr(&path, |reader| {
+            let new_bytes_result: Result<i64, String> = ftp.retr(&path, |reader| {
                 let mut buf = vec![0u8; 64 * 1024]; // 64KB buffer
                 let mut total: i64 = 0;
 
                 loop {
+                    // 每轮读之前检查取消标记，避免一个大文件下载到一半时完全没办法中止
+                    if !running.load(std::sync::atomic::Ordering::Relaxed) {
+                        bytes_so_far.set(total);
+                        return Err(suppaftp::FtpError::ConnectionError(
+                            std::io::Error::new(std::io::ErrorKind::Other, "下载已取消")
+                        ));
+                    }
+
                     let n = reader.read(&mut buf)
                         .map_err(|e| suppaftp::FtpError::ConnectionError(e))?;
                     if n == 0 {
@@ -103,30 +436,51 @@ impl Downloader for FTPDownloader {
                         .map_err(|e| suppaftp::FtpError::ConnectionError(e))?;
 
                     total += n as i64;
+                    bytes_so_far.set(total);
+                    if let Some(ref monitor) = monitor {
+                        rt_handle.block_on(monitor.add_bytes(n as i64));
+                    }
                 }
 
                 Ok(total)
-            }).map_err(|e| format!("FTP 下载失败: {}", e))?;
+            }).map_err(|e| format!("FTP 下载失败: {}", e));
+
+            let new_bytes = match new_bytes_result {
+                Ok(new_bytes) => new_bytes,
+                Err(e) => {
+                    // 取消/出错都要把已经写盘的前缀字节数落回续传状态，否则下次重试时
+                    // `.part` 文件实际长度和记录的 `downloaded` 不一致，整份续传进度作废
+                    state.downloaded = resume_offset + bytes_so_far.get();
+                    let _ = state.save(&save_path);
+                    return Err(e);
+                }
+            };
 
-            let elapsed = start_time.elapsed().as_secs_f64();
+            let downloaded = resume_offset + new_bytes;
+            state.downloaded = downloaded;
+            let _ = state.save(&save_path);
 
-            // 断开连接
-            let _ = ftp.quit();
+            let elapsed = start_time.elapsed().as_secs_f64();
 
             // 验证大小
             if downloaded != file_size {
+                let _ = ftp.quit();
                 return Err(format!("FTP 下载不完整: {}/{} bytes", downloaded, file_size));
             }
 
-            Ok((downloaded, elapsed))
+            std::fs::rename(&part, &save_path)
+                .map_err(|e| format!("重命名文件失败: {}", e))?;
+            ResumeState::delete(&save_path);
+
+            // 下载成功：把连接交还给调用方放回池子，而不是 quit 断开，
+            // 这样同一目标的下一次下载能跳过三次握手 + 登录
+            Ok((downloaded, elapsed, Some(ftp)))
         }).await.map_err(|e| format!("FTP 下载线程异常: {}", e))?;
 
         match result {
-            Ok((downloaded, elapsed)) => {
-                // 更新进度监控
-                if let Some(ref monitor) = monitor {
-                    monitor.set_total_bytes(downloaded);
-                    monitor.add_bytes(downloaded).await;
+            Ok((downloaded, elapsed, conn)) => {
+                if let Some(conn) = conn {
+                    FTP_POOL.release(pool_key, conn, pool_max_size).await;
                 }
 
                 let speed_mbps = if elapsed > 0.0 {
@@ -146,8 +500,12 @@ impl Downloader for FTPDownloader {
         "FTP".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
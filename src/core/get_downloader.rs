@@ -36,8 +36,8 @@ pub async fn get_downloader(
 
     match scheme {
         Protocol::Http => {
-            // 探测服务器是否支持 HTTP/3 (Alt-Svc: h3)
-            // 使用 500ms 超时的 HEAD 请求，失败或无 h3 则回退到 HTTPDownloader
+            // 探测服务器是否支持 HTTP/3 (Alt-Svc: h3)：已知结果（无论正负）直接用缓存，
+            // 否则发一次 HEAD 探测并把结果连同 max-age 记入缓存，减少重复探测的延迟
             if probe_h3_support(&url).await {
                 eprintln!("服务器支持 HTTP/3，使用 QUIC 下载");
                 Box::new(HTTP3Downloader::new(config).await) as Box<dyn Downloader>
@@ -56,9 +56,13 @@ pub async fn get_downloader(
     }
 }
 
-/// 发送 HEAD 请求，检查 Alt-Svc 头是否包含 h3
-/// 超时 800ms，失败直接返回 false（不阻塞下载）
+/// 查进程级 Alt-Svc 缓存；命中（无论正负）直接返回，否则发一次 800ms 超时的 HEAD 探测，
+/// 把结果和 Alt-Svc 的 `ma=` 有效期一起记入缓存，避免同一个主机每次下载都重新探测
 async fn probe_h3_support(url: &str) -> bool {
+    if let Some(cached) = super::alt_svc_cache::lookup(url) {
+        return cached;
+    }
+
     use std::time::Duration;
 
     // 复用全局 HTTP client（如果可用），否则临时创建
@@ -70,20 +74,34 @@ async fn probe_h3_support(url: &str) -> bool {
         Err(_) => return false,
     };
 
-    match client.head(url).send().await {
-        Ok(resp) => {
-            // 检查 Alt-Svc 头：h3="..." 或 h3-29="..."
-            resp.headers()
-                .get("alt-svc")
-                .and_then(|v| v.to_str().ok())
-                .map(|s| {
-                    let lower = s.to_lowercase();
-                    lower.contains("h3=") || lower.contains("h3-")
-                })
-                .unwrap_or(false)
-        }
-        Err(_) => false,
-    }
+    let (supports_h3, max_age_secs) = match client.head(url).send().await {
+        Ok(resp) => resp.headers()
+            .get("alt-svc")
+            .and_then(|v| v.to_str().ok())
+            .map(parse_alt_svc)
+            .unwrap_or((false, super::alt_svc_cache::DEFAULT_MAX_AGE_SECS)),
+        Err(_) => (false, super::alt_svc_cache::DEFAULT_MAX_AGE_SECS),
+    };
+
+    super::alt_svc_cache::record(url, supports_h3, max_age_secs);
+    supports_h3
+}
+
+/// 解析 Alt-Svc 头，例如 `h3=":443"; ma=86400, h3-29=":443"; ma=86400`:
+/// 是否包含 h3/h3-* 条目，以及跟在后面的 `ma=` 有效期（秒，缺省用默认值）
+fn parse_alt_svc(value: &str) -> (bool, u64) {
+    let lower = value.to_lowercase();
+    let supports_h3 = lower.contains("h3=") || lower.contains("h3-");
+
+    let max_age = lower.find("ma=")
+        .and_then(|idx| {
+            let rest = &lower[idx + 3..];
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse::<u64>().ok()
+        })
+        .unwrap_or(super::alt_svc_cache::DEFAULT_MAX_AGE_SECS);
+
+    (supports_h3, max_age)
 }
 
 /// 支持的下载协议枚举
@@ -0,0 +1,36 @@
+//! 极简 glob 匹配：只支持 `*`（任意长度，包括 0 个字符）和 `?`（任意单个字符），
+//! 用于目录递归下载的 include/exclude 过滤器。不支持 `[abc]` 字符集或 `**` 跨目录
+//! 通配这类完整 shell glob 语义，这里的场景够用就不为此引入额外依赖。
+
+/// 路径分隔符统一按 `/` 处理，和远程 FTP/SFTP 路径、相对路径拼接时使用的分隔符一致
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            match_from(&pattern[1..], text) || (!text.is_empty() && match_from(pattern, &text[1..]))
+        }
+        Some('?') => !text.is_empty() && match_from(&pattern[1..], &text[1..]),
+        Some(c) => !text.is_empty() && text[0] == *c && match_from(&pattern[1..], &text[1..]),
+    }
+}
+
+/// 递归目录下载时判断某个相对路径是否应该下载：先看 `exclude`（命中就跳过，
+/// 优先级最高），再看 `include`（给了就必须命中，没给就放行一切）
+pub fn should_download(rel_path: &str, include: &Option<String>, exclude: &Option<String>) -> bool {
+    if let Some(pattern) = exclude {
+        if glob_match(pattern, rel_path) {
+            return false;
+        }
+    }
+
+    match include {
+        Some(pattern) => glob_match(pattern, rel_path),
+        None => true,
+    }
+}
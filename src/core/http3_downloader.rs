@@ -6,6 +6,7 @@ use bytes::Buf;
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
 use super::performance_monitor::PerformanceMonitor;
+use super::resumable_download::ResumableFile;
 
 /// HTTP/3 下载器
 /// 使用 QUIC (quinn) + HTTP/3 (h3) 进行下载
@@ -21,7 +22,7 @@ impl HTTP3Downloader {
         HTTP3Downloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
@@ -109,14 +110,54 @@ impl Downloader for HTTP3Downloader {
             let _ = futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
         });
 
-        // 构建 HTTP/3 GET 请求
-        let request = http::Request::builder()
-            .method(http::Method::GET)
+        // 先发一次 HEAD 探测，拿到 Accept-Ranges/ETag/Last-Modified，决定能否续传
+        let head_request = http::Request::builder()
+            .method(http::Method::HEAD)
             .uri(url_str.as_str())
             .header("host", &host)
             .header("user-agent", "TTHSDNext/1.0 (HTTP/3)")
-            .header("accept", "*/*")
             .body(())
+            .map_err(|e| format!("HTTP/3 HEAD 请求构建失败: {}", e))?;
+
+        let head_headers = match send_request.send_request(head_request).await {
+            Ok(mut head_stream) => {
+                let _ = head_stream.finish().await;
+                head_stream.recv_response().await.ok().map(|r| r.headers().clone())
+            }
+            Err(_) => None,
+        };
+
+        let accept_ranges = head_headers.as_ref()
+            .and_then(|h| h.get("accept-ranges"))
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.eq_ignore_ascii_case("bytes"))
+            .unwrap_or(false);
+        let total_size = head_headers.as_ref()
+            .and_then(|h| h.get("content-length"))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<i64>().ok());
+        let etag = head_headers.as_ref()
+            .and_then(|h| h.get("etag"))
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let last_modified = head_headers.as_ref()
+            .and_then(|h| h.get("last-modified"))
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let mut resumable = ResumableFile::open(url_str, &task.save_path, total_size, etag, last_modified).await?;
+
+        // 构建 HTTP/3 GET 请求，有续传进度时带上 Range
+        let mut request_builder = http::Request::builder()
+            .method(http::Method::GET)
+            .uri(url_str.as_str())
+            .header("host", &host)
+            .header("user-agent", "TTHSDNext/1.0 (HTTP/3)")
+            .header("accept", "*/*");
+        if accept_ranges && resumable.resume_offset() > 0 {
+            request_builder = request_builder.header("range", format!("bytes={}-", resumable.resume_offset()));
+        }
+        let request = request_builder.body(())
             .map_err(|e| format!("HTTP/3 请求构建失败: {}", e))?;
 
         let mut stream = send_request.send_request(request).await
@@ -132,41 +173,32 @@ impl Downloader for HTTP3Downloader {
         let status = response.status();
         eprintln!("HTTP/3 响应状态: {}", status);
 
+        if resumable.resume_offset() > 0 && status.as_u16() != 206 {
+            return Err("HTTP/3 服务器不支持续传 (未返回 206)，请删除 .part 文件后重试".into());
+        }
+
         if !status.is_success() {
             return Err(format!("HTTP/3 服务器返回错误: {}", status).into());
         }
 
-        // 从响应头获取 Content-Length
-        let total = response.headers()
-            .get("content-length")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or(0);
-
-        if total > 0 {
+        if let Some(total) = resumable.total_size() {
             if let Some(ref monitor) = self.monitor {
                 monitor.set_total_bytes(total);
             }
         }
 
-        // 创建输出文件
-        let mut file = tokio::fs::File::create(&task.save_path).await
-            .map_err(|e| format!("创建文件失败: {}", e))?;
-
         // 流式读取响应体
-        let mut downloaded: i64 = 0;
-        use tokio::io::AsyncWriteExt;
+        let resumed_from = resumable.resume_offset();
+        let mut downloaded: i64 = resumed_from;
 
         loop {
             match stream.recv_data().await {
                 Ok(Some(mut data)) => {
                     // data implements bytes::Buf
-                    use tokio::io::AsyncWriteExt;
                     while data.has_remaining() {
                         let chunk_len = data.remaining().min(65536);
                         let chunk = data.chunk()[..chunk_len].to_vec();
-                        file.write_all(&chunk).await
-                            .map_err(|e| format!("写入文件失败: {}", e))?;
+                        resumable.write_all(&chunk).await?;
                         data.advance(chunk_len);
                         downloaded += chunk_len as i64;
                         if let Some(ref monitor) = self.monitor {
@@ -178,6 +210,8 @@ impl Downloader for HTTP3Downloader {
                 Err(e) => return Err(format!("HTTP/3 数据读取失败: {}", e).into()),
             }
         }
+        resumable.record_progress();
+        resumable.finish().await?;
 
         eprintln!("HTTP/3 下载完成: {:.2} MB", downloaded as f64 / 1024.0 / 1024.0);
         Ok(())
@@ -187,8 +221,12 @@ impl Downloader for HTTP3Downloader {
         "HTTP/3".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
@@ -11,6 +13,12 @@ use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadChunk, DownloadConfig, Event, EventType};
 use super::performance_monitor::PerformanceMonitor;
 use super::send_message::send_message;
+use super::mirror_pool::MirrorPool;
+use super::download_journal::{DownloadJournal, WorkerProgress};
+use super::rate_limiter::RateLimiter;
+use super::archive_extractor::{self, ArchiveFormat};
+use super::checksum;
+use super::retry::{self, NonRetryableError};
 
 const STALL_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -34,6 +42,12 @@ async fn get_global_client() -> Client {
     }).await.clone()
 }
 
+/// `get_remote_info` 的返回值：文件大小和用于判断续传有效性的校验标识
+struct RemoteInfo {
+    size: i64,
+    validator: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadSnapshot {
     #[serde(rename = "downloaded")]
@@ -52,22 +66,53 @@ pub struct DownloadSnapshot {
     pub average_speed_bps: f64,
     #[serde(rename = "elapsed_seconds")]
     pub elapsed_seconds: f64,
+    /// 当前生效的限速上限 (字节/秒)，None 表示未限速
+    #[serde(rename = "max_speed_bps")]
+    pub max_speed_bps: Option<u64>,
+    /// 距离下载完成的预估剩余时间，当前速度为 0 时无法估算
+    #[serde(rename = "estimated_remaining_seconds")]
+    pub estimated_remaining_seconds: Option<f64>,
+    /// 最近一个通知窗口内的吞吐量 (字节/秒)，区别于全局监控的瞬时/平均速度
+    #[serde(rename = "last_throughput_bps")]
+    pub last_throughput_bps: f64,
+    /// 这是第几次产出快照，单调递增，可用于客户端去重/排序
+    #[serde(rename = "notification_count")]
+    pub notification_count: u64,
 }
 
+/// 两次快照推送之间的最短间隔，避免高频分块更新把 UI 刷爆
+const PROGRESS_PUSH_THROTTLE: Duration = Duration::from_millis(250);
+
+#[derive(Clone)]
 pub struct DownloadStatus {
     total_size: i64,
     downloaded: Arc<RwLock<i64>>,
     error_message: Arc<RwLock<Option<String>>>,
     start_time: Instant,
+    max_speed_bps: Option<u64>,
+    notification_count: Arc<AtomicU64>,
+    last_notify_downloaded: Arc<RwLock<i64>>,
+    last_notify_time: Arc<RwLock<Instant>>,
+    last_push_at: Arc<RwLock<Instant>>,
 }
 
 impl DownloadStatus {
     pub fn new(total_size: i64) -> Self {
+        Self::with_max_speed(total_size, None)
+    }
+
+    pub fn with_max_speed(total_size: i64, max_speed_bps: Option<u64>) -> Self {
+        let now = Instant::now();
         DownloadStatus {
             total_size,
             downloaded: Arc::new(RwLock::new(0)),
             error_message: Arc::new(RwLock::new(None)),
-            start_time: Instant::now(),
+            start_time: now,
+            max_speed_bps,
+            notification_count: Arc::new(AtomicU64::new(0)),
+            last_notify_downloaded: Arc::new(RwLock::new(0)),
+            last_notify_time: Arc::new(RwLock::new(now)),
+            last_push_at: Arc::new(RwLock::new(now.checked_sub(PROGRESS_PUSH_THROTTLE).unwrap_or(now))),
         }
     }
 
@@ -103,6 +148,25 @@ impl DownloadStatus {
 
         let is_finished = downloaded >= self.total_size || error_message.is_some();
 
+        let estimated_remaining_seconds = if current_speed > 0.0 {
+            Some((self.total_size - downloaded).max(0) as f64 / current_speed)
+        } else {
+            None
+        };
+
+        let now = Instant::now();
+        let last_throughput_bps = {
+            let mut last_time = self.last_notify_time.write().await;
+            let mut last_downloaded = self.last_notify_downloaded.write().await;
+            let elapsed = now.duration_since(*last_time).as_secs_f64();
+            let bytes_diff = downloaded - *last_downloaded;
+            let throughput = if elapsed > 0.0 { bytes_diff as f64 / elapsed } else { 0.0 };
+            *last_time = now;
+            *last_downloaded = downloaded;
+            throughput
+        };
+        let notification_count = self.notification_count.fetch_add(1, Ordering::Relaxed) + 1;
+
         DownloadSnapshot {
             downloaded,
             total_size: self.total_size,
@@ -112,6 +176,22 @@ impl DownloadStatus {
             current_speed_bps: current_speed,
             average_speed_bps: average_speed,
             elapsed_seconds: self.start_time.elapsed().as_secs_f64(),
+            max_speed_bps: self.max_speed_bps,
+            estimated_remaining_seconds,
+            last_throughput_bps,
+            notification_count,
+        }
+    }
+
+    /// 节流检查：距上次推送是否已经超过 `PROGRESS_PUSH_THROTTLE`，是则顺带刷新时间戳
+    async fn should_push(&self) -> bool {
+        let mut last = self.last_push_at.write().await;
+        let now = Instant::now();
+        if now.duration_since(*last) >= PROGRESS_PUSH_THROTTLE {
+            *last = now;
+            true
+        } else {
+            false
         }
     }
 }
@@ -121,6 +201,9 @@ pub struct HTTPDownloader {
     client: Client,
     monitor: Option<Arc<PerformanceMonitor>>,
     status: Option<DownloadStatus>,
+    /// 订阅者注册的推送通道；每次分块批量更新都会尝试往这里投递一份节流后的快照，
+    /// 让调用方无需轮询 `get_snapshot` 就能拿到近实时的进度/ETA
+    progress_tx: Option<mpsc::Sender<DownloadSnapshot>>,
 }
 
 /// 动态分片工作者 - 跟踪每个分块的实时下载进度
@@ -144,18 +227,37 @@ impl ChunkWorker {
         }
     }
 
+    /// 从续传日志里记录的进度重建 worker，下载会从 `progress` 而不是 `start` 继续
+    fn from_progress(start: i64, progress: i64, end: i64) -> Self {
+        ChunkWorker {
+            start_pos: start,
+            progress: Arc::new(AtomicI64::new(progress)),
+            end_pos: Arc::new(AtomicI64::new(end)),
+        }
+    }
+
     /// 剩余未下载的字节数
     fn remaining(&self) -> i64 {
         let end = self.end_pos.load(Ordering::Relaxed);
         let progress = self.progress.load(Ordering::Relaxed);
         (end - progress).max(0)
     }
+
+    fn to_progress(&self) -> WorkerProgress {
+        WorkerProgress {
+            start_pos: self.start_pos,
+            progress: self.progress.load(Ordering::Relaxed),
+            end_pos: self.end_pos.load(Ordering::Relaxed),
+        }
+    }
 }
 
 /// 最小可切分大小 (2MB) - 低于此阈值不再切分
 const MIN_REASSIGN_SIZE: i64 = 2 * 1024 * 1024;
 /// 最大并发连接数上限
 const MAX_CONNECTIONS: usize = 64;
+/// 一个镜像在一轮下载中连续失败超过这个次数之后被标记为 dropped
+const MIRROR_DROP_THRESHOLD: i64 = 3;
 impl HTTPDownloader {
     pub async fn new(config: Arc<RwLock<DownloadConfig>>) -> Self {
         let client = get_global_client().await;
@@ -164,16 +266,28 @@ impl HTTPDownloader {
         HTTPDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             client,
             monitor,
             status: None,
+            progress_tx: None,
         }
     }
 
+    /// 注册一个推送通道：下载过程中每次分块批量更新都会尝试向它投递一份节流后的快照
+    pub fn set_progress_sender(&mut self, tx: mpsc::Sender<DownloadSnapshot>) {
+        self.progress_tx = Some(tx);
+    }
+
     async fn get_file_size(&self, url: &str) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.get_remote_info(url).await?.size)
+    }
+
+    /// 发一次 HEAD 请求，同时取文件大小和服务器的校验标识 (ETag 优先，没有则退化为
+    /// Last-Modified)，后者用来判断续传日志记录的进度是否还对应同一个远端文件
+    async fn get_remote_info(&self, url: &str) -> Result<RemoteInfo, Box<dyn std::error::Error + Send + Sync>> {
         let response = self.client
             .head(url)
             .send()
@@ -194,7 +308,14 @@ impl HTTPDownloader {
             return Err("Invalid content length".into());
         }
 
-        Ok(content_length)
+        let validator = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .or_else(|| response.headers().get(reqwest::header::LAST_MODIFIED))
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        Ok(RemoteInfo { size: content_length, validator })
     }
 
     fn create_chunks(file_size: i64, chunk_size: i64, thread_count: usize) -> Vec<DownloadChunk> {
@@ -224,16 +345,98 @@ impl HTTPDownloader {
         chunks
     }
 
-    /// 下载一个分块 (动态版本)
-    /// 读取 worker 的 atomic end_pos，这样主线程可以随时缩减我们的工作范围
+    /// 下载一个分块 (动态版本), 带多镜像故障转移和指数退避重试
+    ///
+    /// 每次尝试都从 `mirrors` 轮询取一个未被 drop 的镜像。重新发起请求时总是从
+    /// `progress.load()` 而不是分块原始的 `start` 续传，避免已写入的字节被重复下载。
+    /// 一个镜像连续失败超过阈值会被标记为 dropped 并广播一个进度事件；只有当所有
+    /// 镜像都拒绝过这个分块，或者重试预算耗尽，才把错误真正返回给上层（走
+    /// `EventType::Err`）。404/416 这类明确不可重试的状态码会立刻放弃，不消耗重试预算。
     async fn download_chunk_dynamic(
         &self,
         task: &DownloadTask,
+        end_pos: Arc<AtomicI64>,
+        progress: Arc<AtomicI64>,
+        downloaded_size: Arc<RwLock<i64>>,
+        total_size: i64,
+        mirrors: Arc<MirrorPool>,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        max_retries: usize,
+        max_retry_elapsed: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut attempt = 0usize;
+        let started = Instant::now();
+
+        loop {
+            let mirror_url = match mirrors.next_mirror() {
+                Some(url) => url,
+                None => return Err("所有镜像均已被放弃，分块无法下载".into()),
+            };
+
+            let resume_from = progress.load(Ordering::Relaxed);
+
+            // 在真正发起请求前从全局连接配额里取一个许可，请求结束后（无论成败）
+            // 随 permit 一起释放，让同时运行的多个下载任务共用同一个连接上限
+            let _connection_permit = super::scheduler::global_connection_semaphore()
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("全局连接信号量已关闭");
+
+            let result = self.download_chunk_from_mirror(
+                &mirror_url, &task.save_path, resume_from, end_pos.clone(),
+                progress.clone(), downloaded_size.clone(), total_size, rate_limiter.clone(),
+            ).await;
+            drop(_connection_permit);
+
+            match result {
+                Ok(()) => {
+                    mirrors.record_success(&mirror_url);
+                    return Ok(());
+                }
+                Err(e) => {
+                    if e.downcast_ref::<NonRetryableError>().is_some() {
+                        return Err(format!("分块下载失败，不可重试的错误: {}", e).into());
+                    }
+
+                    let just_dropped = mirrors.record_failure(&mirror_url);
+                    if just_dropped {
+                        self.send_mirror_dropped_message(&mirror_url).await;
+                    }
+                    if mirrors.all_dropped() {
+                        return Err(format!("分块下载失败，所有镜像都已被放弃: {}", e).into());
+                    }
+
+                    attempt += 1;
+                    if retry::retry_budget_exhausted(attempt, max_retries, started, max_retry_elapsed) {
+                        return Err(format!(
+                            "分块下载失败，已达到最大重试次数 {} 或耗时预算 {:?}: {}",
+                            max_retries, max_retry_elapsed, e
+                        ).into());
+                    }
+
+                    let jittered = retry::backoff_delay(attempt);
+                    eprintln!(
+                        "镜像 {} 下载分块失败 ({}), 第 {}/{} 次重试, {:?} 后从偏移 {} 继续",
+                        mirror_url, e, attempt, max_retries, jittered, resume_from,
+                    );
+                    tokio::time::sleep(jittered).await;
+                }
+            }
+        }
+    }
+
+    /// 针对单个镜像 URL 做一次分块下载尝试
+    async fn download_chunk_from_mirror(
+        &self,
+        url: &str,
+        save_path: &str,
         start: i64,
         end_pos: Arc<AtomicI64>,
         progress: Arc<AtomicI64>,
         downloaded_size: Arc<RwLock<i64>>,
         _total_size: i64,
+        rate_limiter: Option<Arc<RateLimiter>>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let current_end = end_pos.load(Ordering::Relaxed);
         let mut headers = HeaderMap::new();
@@ -245,13 +448,17 @@ impl HTTPDownloader {
         headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
 
         let response = self.client
-            .get(&task.url)
+            .get(url)
             .headers(headers)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            return Err(format!("Bad status: {}", response.status()).into());
+            let status = response.status();
+            if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                return Err(Box::new(NonRetryableError(format!("Bad status: {}", status))));
+            }
+            return Err(format!("Bad status: {}", status).into());
         }
 
         let last_read = Arc::new(RwLock::new(Instant::now()));
@@ -276,7 +483,7 @@ impl HTTPDownloader {
 
         let mut writer = OpenOptions::new()
             .write(true)
-            .open(&task.save_path).await?;
+            .open(save_path).await?;
 
         writer.seek(std::io::SeekFrom::Start(start as u64)).await?;
 
@@ -302,6 +509,9 @@ impl HTTPDownloader {
                 // 只写入到 dynamic_end 为止
                 let usable = (dynamic_end + 1 - current_pos).max(0) as usize;
                 if usable > 0 {
+                    if let Some(ref limiter) = rate_limiter {
+                        limiter.acquire(usable as i64).await;
+                    }
                     writer.write_all(&bytes[..usable]).await?;
                     local_downloaded += usable as i64;
                     current_pos += usable as i64;
@@ -309,6 +519,9 @@ impl HTTPDownloader {
                 break; // 主线程已经把我们的范围缩减了，停止下载
             }
 
+            if let Some(ref limiter) = rate_limiter {
+                limiter.acquire(bytes_len).await;
+            }
             writer.write_all(&bytes).await?;
             local_downloaded += bytes_len;
             current_pos += bytes_len;
@@ -325,6 +538,8 @@ impl HTTPDownloader {
                     monitor.add_bytes(local_downloaded).await;
                 }
 
+                self.push_progress_snapshot().await;
+
                 local_downloaded = 0;
             }
 
@@ -365,6 +580,48 @@ impl HTTPDownloader {
             let _ = send_message(event, data.into_iter().map(|(k, v)| (k, v)).collect(), config, &self.base.ws_client, &self.base.socket_client).await;
         }
     }
+
+    /// 一个镜像因连续失败过多被放弃时，广播一个 `Update` 事件让调用方知道
+    async fn send_mirror_dropped_message(&self, mirror_url: &str) {
+        if let Some(ref config) = self.base.config {
+            let event = Event {
+                event_type: EventType::Update,
+                name: "MirrorDropped".to_string(),
+                show_name: String::new(),
+                id: String::new(),
+            };
+
+            let mut data = HashMap::new();
+            data.insert("Mirror".to_string(), serde_json::Value::String(mirror_url.to_string()));
+
+            let _ = send_message(event, data, config, &self.base.ws_client, &self.base.socket_client).await;
+        }
+    }
+
+    /// 节流地向订阅者推送一份最新快照；没有注册订阅者或还没到推送间隔时什么都不做
+    async fn push_progress_snapshot(&self) {
+        let (status, tx) = match (&self.status, &self.progress_tx) {
+            (Some(status), Some(tx)) => (status, tx),
+            _ => return,
+        };
+
+        if !status.should_push().await {
+            return;
+        }
+
+        let (current_speed, average_speed) = if let Some(ref monitor) = self.monitor {
+            let stats = monitor.get_stats().await;
+            (
+                stats.get("current_speed_bps").and_then(|v| v.as_f64()).unwrap_or(0.0),
+                stats.get("average_speed_bps").and_then(|v| v.as_f64()).unwrap_or(0.0),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        let snapshot = status.snapshot(current_speed, average_speed).await;
+        let _ = tx.try_send(snapshot);
+    }
 }
 
 impl Default for BaseDownloader {
@@ -378,7 +635,8 @@ impl Default for BaseDownloader {
             ws_client: None,
             socket_client: None,
             config: None,
-            running: true,
+            running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            thread_count: Arc::new(std::sync::atomic::AtomicUsize::new(1)),
         }
     }
 }
@@ -386,15 +644,45 @@ impl Default for BaseDownloader {
 #[async_trait::async_trait]
 impl Downloader for HTTPDownloader {
     async fn download(&mut self, task: &DownloadTask) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let file_size = self.get_file_size(&task.url).await?;
+        let extract_format = if let Some(format) = task.extract {
+            Some(format)
+        } else if let Some(ref config) = self.base.config {
+            config.read().await.extract
+        } else {
+            None
+        };
+
+        if let Some(format) = extract_format {
+            return self.download_with_extract(task, format).await;
+        }
+
+        let remote = self.get_remote_info(&task.url).await?;
+        let file_size = remote.size;
+
+        let max_speed_bps = if let Some(ref config) = self.base.config {
+            config.read().await.max_speed_bps
+        } else {
+            None
+        };
+
+        self.status = Some(DownloadStatus::with_max_speed(file_size, max_speed_bps));
 
-        self.status = Some(DownloadStatus::new(file_size));
-        
         // 更新全局监控的总大小
         if let Some(ref monitor) = self.monitor {
             monitor.set_total_bytes(file_size);
         }
 
+        // 如果存在续传日志且校验标识和大小都匹配，复用里面记录的分块进度；
+        // 否则（包括日志不存在）都视为全新下载，丢弃可能过期的日志
+        let journal = DownloadJournal::load(&task.save_path)
+            .filter(|j| j.matches(file_size, &remote.validator));
+        if journal.is_none() {
+            DownloadJournal::delete(&task.save_path);
+        }
+
+        // 下载开始前先确认目标磁盘放得下，避免写到一半才因为磁盘满失败
+        super::disk_guard::check_capacity(&task.save_path, file_size)?;
+
         let file = OpenOptions::new()
             .write(true)
             .create(true)
@@ -402,10 +690,10 @@ impl Downloader for HTTPDownloader {
 
         // FAT32 文件系统单文件上限为 4GB，超过时给出明确提示
         const FAT32_MAX_FILE_SIZE: i64 = 4_294_967_295; // 4GB - 1 byte
-        
-        // 尝试预分配文件大小（提升多线程分块写入性能）
+
+        // 尝试预分配文件大小（fallocate，不支持时退化为 set_len，提升多线程分块写入性能）
         // 如果失败（例如 FAT32 文件系统不支持大文件），则跳过预分配继续下载
-        if let Err(e) = file.set_len(file_size as u64).await {
+        if let Err(e) = super::disk_guard::preallocate(&file, file_size as u64).await {
             if file_size > FAT32_MAX_FILE_SIZE {
                 return Err(format!(
                     "文件大小 ({:.2} GB) 超过 FAT32 文件系统的 4GB 限制，请将目标路径改为 NTFS/exFAT 分区",
@@ -421,6 +709,9 @@ impl Downloader for HTTPDownloader {
         } else {
             num_cpus::get() * 2
         };
+        // 动态分片重分配循环之后会不断地从这里读取当前值，而不是只读一次就定死，
+        // 这样 `SetThreadCount` 控制指令在下载进行中调整的并发上限才能生效
+        self.base.thread_count.store(thread_count.max(1), Ordering::Relaxed);
 
         let chunk_size = if let Some(ref config) = self.base.config {
             let cfg = config.read().await;
@@ -429,13 +720,39 @@ impl Downloader for HTTPDownloader {
             10 * 1024 * 1024
         };
 
-        let chunks = Self::create_chunks(file_size, chunk_size as i64, thread_count);
-        let downloaded_size = Arc::new(RwLock::new(0i64));
+        let (max_retries, max_retry_elapsed) = if let Some(ref config) = self.base.config {
+            let cfg = config.read().await;
+            (cfg.max_retries, Duration::from_secs(cfg.max_retry_elapsed_secs))
+        } else {
+            (super::downloader::DEFAULT_MAX_RETRIES, retry::DEFAULT_MAX_RETRY_ELAPSED)
+        };
+
+        let mirrors = Arc::new(MirrorPool::new(task.all_mirrors(), MIRROR_DROP_THRESHOLD));
+        // 限速是按任务共享的，所有 worker (包括后续动态切分出来的) 都从同一个令牌桶扣减，
+        // 因此并发数再怎么增长，聚合速度也不会超过 `max_speed_bps`
+        let rate_limiter = max_speed_bps.map(|r| Arc::new(RateLimiter::new(r)));
+
+        // 有可用的续传日志时，从记录的进度重建 worker (已完成的分块直接跳过不再下载)；
+        // 否则按老路径重新切分整个文件
+        let (mut workers, initial_downloaded): (Vec<Arc<ChunkWorker>>, i64) = if let Some(journal) = journal {
+            eprintln!("发现可续传的下载进度日志: {} 个分块", journal.workers.len());
+            let initial_downloaded = journal.workers.iter()
+                .map(|w| (w.progress - w.start_pos).max(0))
+                .sum();
+            let workers = journal.workers.iter()
+                .filter(|w| w.progress <= w.end_pos)
+                .map(|w| Arc::new(ChunkWorker::from_progress(w.start_pos, w.progress, w.end_pos)))
+                .collect();
+            (workers, initial_downloaded)
+        } else {
+            let chunks = Self::create_chunks(file_size, chunk_size as i64, thread_count);
+            let workers = chunks.iter()
+                .map(|c| Arc::new(ChunkWorker::new(c.start_offset, c.end_offset)))
+                .collect();
+            (workers, 0)
+        };
 
-        // 创建动态分片工作者
-        let workers: Vec<Arc<ChunkWorker>> = chunks.iter().map(|c| {
-            Arc::new(ChunkWorker::new(c.start_offset, c.end_offset))
-        }).collect();
+        let downloaded_size = Arc::new(RwLock::new(initial_downloaded));
 
         let mut join_set = tokio::task::JoinSet::new();
         let mut active_count = 0usize;
@@ -444,14 +761,15 @@ impl Downloader for HTTPDownloader {
             let task_clone = task.clone();
             let downloaded_size_clone = downloaded_size.clone();
             let self_clone = self.clone_downloader();
-            let start = worker.start_pos;
             let end_pos = worker.end_pos.clone();
             let progress = worker.progress.clone();
+            let mirrors_clone = mirrors.clone();
+            let rate_limiter_clone = rate_limiter.clone();
 
             join_set.spawn(async move {
                 self_clone.download_chunk_dynamic(
-                    &task_clone, start, end_pos, progress,
-                    downloaded_size_clone, file_size
+                    &task_clone, end_pos, progress,
+                    downloaded_size_clone, file_size, mirrors_clone, rate_limiter_clone, max_retries, max_retry_elapsed
                 ).await
             });
             active_count += 1;
@@ -459,6 +777,21 @@ impl Downloader for HTTPDownloader {
 
         // 动态分片: 当一个 worker 完成时，找到剩余最大的 worker 并切分
         while let Some(result) = join_set.join_next().await {
+            // `running` 由调度器或 FFI 的 pause/cancel 驱动；一旦被置为 false，
+            // 放弃剩余 worker 并把已写入的进度刷到续传日志，方便之后继续
+            if !self.base.running.load(std::sync::atomic::Ordering::Relaxed) {
+                join_set.abort_all();
+                let snapshot = DownloadJournal {
+                    total_size: file_size,
+                    validator: remote.validator.clone(),
+                    workers: workers.iter().map(|w| w.to_progress()).collect(),
+                };
+                if let Err(e) = snapshot.save(&task.save_path) {
+                    eprintln!("写入续传日志失败: {:?}", e);
+                }
+                return Err("下载任务已被取消".into());
+            }
+
             if let Err(e) = result {
                 self.send_error_message(format!("worker error: {:?}", e)).await;
                 if let Some(ref status) = self.status {
@@ -466,8 +799,11 @@ impl Downloader for HTTPDownloader {
                 }
             }
 
-            // 尝试从剩余最大的 worker 切分一半给新 worker
-            if active_count < MAX_CONNECTIONS {
+            // 尝试从剩余最大的 worker 切分一半给新 worker；并发上限取
+            // `base.thread_count`（可能被 `SetThreadCount` 控制指令实时调整过）
+            // 和硬上限 `MAX_CONNECTIONS` 中较小的一个，避免单个任务无限制地占满连接
+            let live_thread_cap = self.base.thread_count.load(Ordering::Relaxed).max(1).min(MAX_CONNECTIONS);
+            if active_count < live_thread_cap {
                 let mut max_remaining = 0i64;
                 let mut max_worker: Option<&Arc<ChunkWorker>> = None;
 
@@ -493,23 +829,36 @@ impl Downloader for HTTPDownloader {
                         let task_clone = task.clone();
                         let downloaded_size_clone = downloaded_size.clone();
                         let self_clone = self.clone_downloader();
-                        let new_start = mid + 1;
                         let new_end_pos = new_worker.end_pos.clone();
                         let new_progress = new_worker.progress.clone();
+                        let mirrors_clone = mirrors.clone();
+                        let rate_limiter_clone = rate_limiter.clone();
 
                         join_set.spawn(async move {
                             self_clone.download_chunk_dynamic(
-                                &task_clone, new_start, new_end_pos, new_progress,
-                                downloaded_size_clone, file_size
+                                &task_clone, new_end_pos, new_progress,
+                                downloaded_size_clone, file_size, mirrors_clone, rate_limiter_clone, max_retries, max_retry_elapsed
                             ).await
                         });
                         active_count += 1;
+                        workers.push(new_worker);
 
                         eprintln!("动态分片: 从 [{}-{}] 切分出 [{}-{}], 当前连接数: {}",
                             current_progress, mid, mid + 1, current_end, active_count);
                     }
                 }
             }
+
+            // 每当有 worker 完成或被重新切分，就把当前进度原子地刷到续传日志，
+            // 这样进程中途退出时只会丢失两次刷新之间的那一小段进度
+            let snapshot = DownloadJournal {
+                total_size: file_size,
+                validator: remote.validator.clone(),
+                workers: workers.iter().map(|w| w.to_progress()).collect(),
+            };
+            if let Err(e) = snapshot.save(&task.save_path) {
+                eprintln!("写入续传日志失败: {:?}", e);
+            }
         }
 
         let current_size = *downloaded_size.read().await;
@@ -517,6 +866,34 @@ impl Downloader for HTTPDownloader {
             return Err(format!("download incomplete: {}/{} bytes", current_size, file_size).into());
         }
 
+        // 各 worker 写入的是不相交的偏移区间，乱序落盘，所以完整性只能在下载完成后
+        // 对落盘文件做一次顺序重读来校验；校验失败时特意不删除续传日志，方便排查
+        if let Some((algo, expected)) = &task.expected_checksum {
+            if let Err(e) = checksum::verify_file(&task.save_path, *algo, expected).await {
+                if let Some(ref status) = self.status {
+                    status.set_error(e.clone()).await;
+                }
+                return Err(e.into());
+            }
+        }
+
+        // 下载完成后按 URL 后缀猜测的归档格式就地解压，解压目标是去掉扩展名的同名目录；
+        // 这与 `download_with_extract` 的边下边解压不是同一条路径 —— 这里读到的已经是
+        // 完整落盘的文件；解压失败时 download 返回该错误，已下载的原始文件仍留在 save_path
+        if let Some(format) = ArchiveFormat::from_url(&task.url) {
+            let target_dir = PathBuf::from(&task.save_path).with_extension("");
+            let save_path = task.save_path.clone();
+            let extract_result = tokio::task::spawn_blocking(move || {
+                archive_extractor::extract_completed_file(Path::new(&save_path), &target_dir, format)
+            })
+            .await
+            .map_err(|e| format!("解压任务 join 失败: {:?}", e))?;
+
+            extract_result?;
+        }
+
+        DownloadJournal::delete(&task.save_path);
+
         Ok(())
     }
 
@@ -524,8 +901,15 @@ impl Downloader for HTTPDownloader {
         "http".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    /// 运行中的下载每轮动态分片重分配都会重新读取 `base.thread_count`，
+    /// 所以调用方直接往这个 `Arc<AtomicUsize>` 里 `store` 就能让 `SetThreadCount`
+    /// 控制指令对一个正在跑的下载实际生效，而不是只影响下一次下载
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
@@ -553,16 +937,94 @@ impl Downloader for HTTPDownloader {
 }
 
 impl HTTPDownloader {
+    /// 边下载边解压: 把下载到的字节块按 offset 顺序推给 `archive_extractor` 的解压线程，
+    /// 而不是先落盘整个归档再二次读取解压，从而把大型压缩包的峰值磁盘占用减半。
+    /// 为了满足解压线程"严格按 offset 顺序接收"的前提，这里退化为单连接顺序下载
+    /// (不使用 `download_chunk_dynamic` 的多连接动态分片)。
+    async fn download_with_extract(
+        &mut self,
+        task: &DownloadTask,
+        format: super::archive_extractor::ArchiveFormat,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let file_size = self.get_file_size(&task.url).await?;
+        self.status = Some(DownloadStatus::new(file_size));
+
+        if let Some(ref monitor) = self.monitor {
+            monitor.set_total_bytes(file_size);
+        }
+
+        let target_dir = std::path::Path::new(&task.save_path)
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .to_path_buf();
+
+        let (tx, handle) = super::archive_extractor::spawn_extract_pipeline(target_dir, format);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36"));
+        headers.insert(RANGE, HeaderValue::from_str(&format!("bytes=0-{}", file_size - 1))?);
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+
+        let response = self.client.get(&task.url).headers(headers).send().await?;
+        if !response.status().is_success() {
+            return Err(format!("Bad status: {}", response.status()).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut offset = 0i64;
+        let mut downloaded = 0i64;
+
+        while let Some(bytes_result) = stream.next().await {
+            let bytes = bytes_result?;
+            let len = bytes.len() as i64;
+
+            let chunk = super::archive_extractor::DataChunk { offset, bytes: bytes.to_vec() };
+            let tx_clone = tx.clone();
+            tokio::task::spawn_blocking(move || tx_clone.send(chunk))
+                .await?
+                .map_err(|_| "解压线程已退出，无法继续喂入数据")?;
+
+            offset += len;
+            downloaded += len;
+
+            if let Some(ref monitor) = self.monitor {
+                monitor.add_bytes(len).await;
+            }
+
+            if let Some(ref status) = self.status {
+                status.add_downloaded(len).await;
+            }
+        }
+
+        // 关闭发送端让解压线程知道数据已经结束
+        drop(tx);
+
+        let extract_result = tokio::task::spawn_blocking(move || handle.join())
+            .await
+            .map_err(|e| format!("解压线程 join 失败: {:?}", e))?
+            .map_err(|e| format!("解压线程 panic: {:?}", e))?;
+
+        extract_result?;
+
+        if downloaded != file_size {
+            return Err(format!("download incomplete: {}/{} bytes", downloaded, file_size).into());
+        }
+
+        Ok(())
+    }
+
     fn clone_downloader(&self) -> Self {
         HTTPDownloader {
             base: BaseDownloader {
                 config: self.base.config.clone(),
-                running: self.base.running,
+                running: self.base.running.clone(),
+                thread_count: self.base.thread_count.clone(),
                 ..Default::default()
             },
             client: self.client.clone(),
             monitor: self.monitor.clone(),
-            status: None,
+            status: self.status.clone(),
+            progress_tx: self.progress_tx.clone(),
         }
     }
 }
\ No newline at end of file
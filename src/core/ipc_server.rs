@@ -0,0 +1,174 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::RwLock;
+
+use super::downloader::{DownloadConfig, DownloadTask, DownloaderCommand, HSDownloader, UA};
+use super::export::{get_downloaders, register_downloader, RUNTIME};
+
+/// 守护进程支持的命令，和 FFI 暴露的控制函数一一对应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "cmd")]
+pub enum IpcCommand {
+    Start {
+        tasks: Vec<DownloadTask>,
+        thread_count: usize,
+        chunk_size_mb: usize,
+        #[serde(default)]
+        max_speed_bps: Option<u64>,
+    },
+    Pause { id: i32 },
+    Resume { id: i32 },
+    Stop { id: i32 },
+    Status { id: i32 },
+    List,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub ok: bool,
+    pub id: Option<i32>,
+    pub data: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+impl IpcResponse {
+    fn ok(id: Option<i32>, data: Option<serde_json::Value>) -> Self {
+        IpcResponse { ok: true, id, data, error: None }
+    }
+
+    fn err(message: impl Into<String>) -> Self {
+        IpcResponse { ok: false, id: None, data: None, error: Some(message.into()) }
+    }
+}
+
+/// 在 `RUNTIME` 上监听一个 Unix Domain Socket，接受长度前缀的 JSON 请求帧，
+/// 分发到和 FFI 共用的全局下载器表，再把 `IpcResponse` 以同样的长度前缀格式写回。
+///
+/// 目前只支持 Unix 域套接字；Windows 命名管道尚未实现。
+pub async fn run(socket_path: String) -> std::io::Result<()> {
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)?;
+
+    loop {
+        let (stream, _addr) = listener.accept().await?;
+        RUNTIME.spawn(async move {
+            if let Err(e) = handle_connection(stream).await {
+                eprintln!("IPC 连接处理出错: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: tokio::net::UnixStream) -> std::io::Result<()> {
+    loop {
+        let mut len_buf = [0u8; 4];
+        if stream.read_exact(&mut len_buf).await.is_err() {
+            return Ok(());
+        }
+        let len = u32::from_be_bytes(len_buf) as usize;
+
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await?;
+
+        let response = match serde_json::from_slice::<IpcCommand>(&payload) {
+            Ok(command) => dispatch(command).await,
+            Err(e) => IpcResponse::err(format!("无法解析命令: {}", e)),
+        };
+
+        let body = serde_json::to_vec(&response).unwrap_or_else(|_| b"{}".to_vec());
+        stream.write_all(&(body.len() as u32).to_be_bytes()).await?;
+        stream.write_all(&body).await?;
+    }
+}
+
+async fn dispatch(command: IpcCommand) -> IpcResponse {
+    match command {
+        IpcCommand::Start { tasks, thread_count, chunk_size_mb, max_speed_bps } => start(tasks, thread_count, chunk_size_mb, max_speed_bps).await,
+        IpcCommand::Pause { id } => send_command(id, DownloaderCommand::Pause).await,
+        IpcCommand::Resume { id } => send_command(id, DownloaderCommand::Resume).await,
+        IpcCommand::Stop { id } => send_command(id, DownloaderCommand::Stop).await,
+        IpcCommand::Status { id } => with_downloader(id, |d| async move {
+            let snapshot = d.read().await.status.snapshot().await;
+            match serde_json::to_value(snapshot) {
+                Ok(value) => IpcResponse::ok(Some(id), Some(value)),
+                Err(e) => IpcResponse::err(e.to_string()),
+            }
+        }).await,
+        IpcCommand::List => {
+            let ids: Vec<i32> = get_downloaders().lock().unwrap().keys().copied().collect();
+            IpcResponse::ok(None, Some(serde_json::json!(ids)))
+        }
+    }
+}
+
+async fn with_downloader<F, Fut>(id: i32, f: F) -> IpcResponse
+where
+    F: FnOnce(Arc<RwLock<HSDownloader>>) -> Fut,
+    Fut: std::future::Future<Output = IpcResponse>,
+{
+    let downloader = get_downloaders().lock().unwrap().get(&id).cloned();
+    match downloader {
+        Some(d) => f(d).await,
+        None => IpcResponse::err(format!("下载器 {} 不存在", id)),
+    }
+}
+
+/// 把 `Pause`/`Resume`/`Stop` 命令投递到下载器的命令队列，立即返回；
+/// 真正的执行结果和事件通过既有的 `send_message` 通道广播，不在这里等待
+async fn send_command(id: i32, command: DownloaderCommand) -> IpcResponse {
+    with_downloader(id, |d| async move {
+        match d.read().await.command_tx.send(command) {
+            Ok(()) => IpcResponse::ok(Some(id), None),
+            Err(e) => IpcResponse::err(e.to_string()),
+        }
+    }).await
+}
+
+async fn start(tasks: Vec<DownloadTask>, thread_count: usize, chunk_size_mb: usize, max_speed_bps: Option<u64>) -> IpcResponse {
+    if tasks.is_empty() {
+        return IpcResponse::err("没有可下载的任务");
+    }
+
+    let config = DownloadConfig {
+        tasks,
+        thread_count,
+        chunk_size_mb,
+        callback_func: None,
+        use_callback_url: false,
+        callback_url: None,
+        use_socket: None,
+        show_name: String::new(),
+        user_agent: UA.to_string(),
+        extract: None,
+        db_path: None,
+        max_speed_bps,
+        max_retries: super::downloader::DEFAULT_MAX_RETRIES,
+        max_concurrent_tasks: super::downloader::DEFAULT_MAX_CONCURRENT_TASKS,
+        max_retry_elapsed_secs: super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS,
+        ed2k_gateways: super::downloader::default_ed2k_gateways(),
+        ws_codec: super::downloader::default_ws_codec(),
+        auth_token: None,
+        seed: None,
+        torrent_session_dir: None,
+        conn_pool_max_size: super::downloader::default_conn_pool_max_size(),
+        conn_pool_idle_timeout_secs: super::downloader::default_conn_pool_idle_timeout_secs(),
+    };
+
+    let (downloader_id, downloader) = register_downloader(HSDownloader::new(config));
+
+    let downloader_clone = downloader.clone();
+    RUNTIME.spawn(async move {
+        if let Err(e) = downloader_clone.read().await.start_download().await {
+            eprintln!("IPC 启动的下载器 {} 失败: {:?}", downloader_id, e);
+        }
+        let mut downloaders = get_downloaders().lock().unwrap();
+        downloaders.remove(&downloader_id);
+        drop(downloaders);
+
+        let _ = downloader_clone.read().await.command_tx.send(DownloaderCommand::Shutdown);
+    });
+
+    IpcResponse::ok(Some(downloader_id), None)
+}
@@ -1,14 +1,99 @@
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
 use std::str::FromStr;
+use futures::StreamExt;
+use reqwest::header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use sha2::{Digest, Sha256};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::RwLock;
 
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
+use super::mirror_pool::MirrorPool;
 use super::performance_monitor::PerformanceMonitor;
+use super::resumable_download::{ResumableFile, ResumeState};
+
+/// 某个镜像连续失败超过这个次数后被 `MirrorPool` 标记为 dropped
+const MIRROR_DROP_THRESHOLD: i64 = 3;
+
+/// 分段下载时参与 HEAD 探测的候选镜像数量上限，避免镜像列表很长时逐个探测太慢
+const MAX_PROBE_MIRRORS: usize = 8;
+
+/// Metalink `<hash type="...">` 支持的摘要算法
+#[derive(Debug, Clone, Copy)]
+enum DigestKind {
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl DigestKind {
+    fn from_type_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "sha-256" | "sha256" => Some(DigestKind::Sha256),
+            "sha-1" | "sha1" => Some(DigestKind::Sha1),
+            "md5" => Some(DigestKind::Md5),
+            _ => None,
+        }
+    }
+}
+
+/// 支持边下载边喂入数据的增量哈希器，下载流结束时直接出摘要，
+/// 不需要像 `checksum::verify_file` 那样在下载完之后再整个重读一遍文件
+enum IncrementalHasher {
+    Sha256(Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+}
+
+impl IncrementalHasher {
+    fn new(kind: DigestKind) -> Self {
+        match kind {
+            DigestKind::Sha256 => IncrementalHasher::Sha256(Sha256::new()),
+            DigestKind::Sha1 => IncrementalHasher::Sha1(sha1::Sha1::new()),
+            DigestKind::Md5 => IncrementalHasher::Md5(md5::Md5::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            IncrementalHasher::Sha256(h) => h.update(data),
+            IncrementalHasher::Sha1(h) => h.update(data),
+            IncrementalHasher::Md5(h) => h.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        match self {
+            IncrementalHasher::Sha256(h) => format!("{:x}", h.finalize()),
+            IncrementalHasher::Sha1(h) => format!("{:x}", h.finalize()),
+            IncrementalHasher::Md5(h) => format!("{:x}", h.finalize()),
+        }
+    }
+}
+
+fn compute_digest(kind: DigestKind, data: &[u8]) -> String {
+    let mut hasher = IncrementalHasher::new(kind);
+    hasher.update(data);
+    hasher.finalize_hex()
+}
+
+/// 从 `<hash type="...">` 列表里选一个我们支持的、优先级最高的整文件摘要
+fn pick_whole_file_digest(file_entry: &metalink::File) -> Option<(DigestKind, String)> {
+    file_entry.hashes.iter()
+        .filter_map(|h| DigestKind::from_type_str(&h.hash_type).map(|kind| (kind, h.value.to_lowercase())))
+        .min_by_key(|(kind, _)| match kind {
+            DigestKind::Sha256 => 0,
+            DigestKind::Sha1 => 1,
+            DigestKind::Md5 => 2,
+        })
+}
 
 /// Metalink 下载器
 /// 支持 Metalink 4.0 (.metalink / .meta4) 格式
-/// 解析 XML 文件提取镜像 URL 列表，选择最优镜像用 HTTP 下载
+/// 解析 XML 文件提取镜像 URL 列表；优先把文件切成多段，用支持 `Range` 的镜像并发抓取，
+/// 真正聚合多个镜像的带宽，而不是只挑一个最优的下载整个文件。
 pub struct MetalinkDownloader {
     base: BaseDownloader,
     monitor: Option<Arc<PerformanceMonitor>>,
@@ -20,12 +105,307 @@ impl MetalinkDownloader {
         MetalinkDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
         }
     }
+
+    /// 按优先级顺序对候选镜像做一次 HEAD 探测，挑出同时满足
+    /// `Accept-Ranges: bytes` 和 `Content-Length == expected_size` 的镜像。
+    /// 不支持 range 的镜像会被直接剔除，下载时退化为整文件 fallback。
+    async fn probe_range_support(client: &reqwest::Client, candidates: &[String], expected_size: i64) -> Vec<String> {
+        let mut handles = Vec::with_capacity(candidates.len());
+
+        for url in candidates.iter().take(MAX_PROBE_MIRRORS) {
+            let client = client.clone();
+            let url = url.clone();
+            handles.push(tokio::spawn(async move {
+                let resp = match client.head(&url).send().await {
+                    Ok(r) => r,
+                    Err(_) => return None,
+                };
+
+                let accepts_ranges = resp.headers()
+                    .get(ACCEPT_RANGES)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.eq_ignore_ascii_case("bytes"))
+                    .unwrap_or(false);
+
+                let content_length = resp.headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<i64>().ok());
+
+                if accepts_ranges && content_length == Some(expected_size) {
+                    Some(url)
+                } else {
+                    None
+                }
+            }));
+        }
+
+        let mut ranged = Vec::new();
+        for handle in handles {
+            if let Ok(Some(url)) = handle.await {
+                ranged.push(url);
+            }
+        }
+        ranged
+    }
+
+    /// 把 `0..file_size` 平均切成 `count` 段 `(start, end)`，`end` 为闭区间
+    fn create_segments(file_size: i64, count: usize) -> Vec<(i64, i64)> {
+        let count = count.max(1).min(file_size.max(1) as usize);
+        let segment_size = file_size / count as i64;
+
+        let mut segments = Vec::with_capacity(count);
+        let mut offset = 0i64;
+        for i in 0..count {
+            let end = if i == count - 1 { file_size - 1 } else { offset + segment_size - 1 };
+            segments.push((offset, end));
+            offset = end + 1;
+        }
+        segments
+    }
+
+    /// 下载单个分段，失败时从 `mirrors` 取下一个镜像重试，最多重试 `max_retries` 次
+    async fn download_segment(
+        client: reqwest::Client,
+        save_path: String,
+        start: i64,
+        end: i64,
+        mirrors: Arc<MirrorPool>,
+        monitor: Option<Arc<PerformanceMonitor>>,
+        max_retries: usize,
+    ) -> Result<(), String> {
+        let mut attempt = 0usize;
+
+        loop {
+            let mirror_url = mirrors.next_mirror().ok_or("所有镜像均已被放弃，分段无法下载")?;
+
+            let result: Result<(), String> = async {
+                let response = client.get(&mirror_url)
+                    .header(RANGE, HeaderValue::from_str(&format!("bytes={}-{}", start, end))
+                        .map_err(|e| format!("构造 Range 头失败: {}", e))?)
+                    .send().await
+                    .map_err(|e| format!("请求镜像 {} 失败: {}", mirror_url, e))?;
+
+                if !response.status().is_success() {
+                    return Err(format!("镜像 {} 返回状态码 {}", mirror_url, response.status()));
+                }
+
+                let mut file = OpenOptions::new().write(true).open(&save_path).await
+                    .map_err(|e| format!("打开文件失败: {}", e))?;
+                file.seek(std::io::SeekFrom::Start(start as u64)).await
+                    .map_err(|e| format!("定位写入偏移失败: {}", e))?;
+
+                let mut stream = response.bytes_stream();
+                while let Some(chunk) = stream.next().await {
+                    let bytes = chunk.map_err(|e| format!("流读取失败: {}", e))?;
+                    file.write_all(&bytes).await.map_err(|e| format!("写入失败: {}", e))?;
+                    if let Some(ref monitor) = monitor {
+                        monitor.add_bytes(bytes.len() as i64).await;
+                    }
+                }
+
+                Ok(())
+            }.await;
+
+            match result {
+                Ok(()) => {
+                    mirrors.record_success(&mirror_url);
+                    return Ok(());
+                }
+                Err(e) => {
+                    mirrors.record_failure(&mirror_url);
+                    if mirrors.all_dropped() {
+                        return Err(format!("分段 [{}-{}] 下载失败，所有镜像都已被放弃: {}", start, end, e));
+                    }
+                    attempt += 1;
+                    if attempt > max_retries {
+                        return Err(format!("分段 [{}-{}] 下载失败，已达到最大重试次数 {}: {}", start, end, max_retries, e));
+                    }
+                    eprintln!("分段 [{}-{}] 下载失败 ({}), 第 {}/{} 次重试, 改用下一个镜像", start, end, e, attempt, max_retries);
+                }
+            }
+        }
+    }
+
+    /// 按分片校验已完成的文件；某一片摘要不匹配时只向镜像池重新请求那一片的字节区间，
+    /// 重取后再校验一次仍不通过就放弃（调用方负责删除文件并把错误交给上层重试）
+    async fn verify_pieces(
+        &self,
+        save_path: &str,
+        pieces: &metalink::Pieces,
+        kind: DigestKind,
+        mirrors: &Arc<MirrorPool>,
+        max_retries: usize,
+    ) -> Result<(), String> {
+        let piece_length = pieces.length;
+        let mut ordered_hashes = pieces.hashes.clone();
+        ordered_hashes.sort_by_key(|h| h.piece);
+        let file_len = tokio::fs::metadata(save_path).await
+            .map_err(|e| format!("读取文件大小失败: {}", e))?.len() as i64;
+
+        for piece_hash in &ordered_hashes {
+            let start = piece_hash.piece as i64 * piece_length;
+            // 最后一片通常不足 piece_length，截到文件末尾
+            let end = (start + piece_length - 1).min(file_len - 1);
+
+            let actual = Self::read_and_digest(save_path, start, end, kind).await?;
+            if actual == piece_hash.value.to_lowercase() {
+                continue;
+            }
+
+            eprintln!("分片 {} 摘要不匹配，从镜像重新获取 [{}-{}]", piece_hash.piece, start, end);
+            Self::download_segment(
+                reqwest::Client::new(), save_path.to_string(), start, end,
+                mirrors.clone(), self.monitor.clone(), max_retries,
+            ).await?;
+
+            let retried = Self::read_and_digest(save_path, start, end, kind).await?;
+            if retried != piece_hash.value.to_lowercase() {
+                return Err(format!("分片 {} 重新获取后摘要仍不匹配", piece_hash.piece));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 从已落盘文件里读出 `[start, end]` 闭区间字节并计算摘要
+    async fn read_and_digest(save_path: &str, start: i64, end: i64, kind: DigestKind) -> Result<String, String> {
+        let mut file = tokio::fs::File::open(save_path).await
+            .map_err(|e| format!("打开文件失败: {}", e))?;
+        file.seek(std::io::SeekFrom::Start(start as u64)).await
+            .map_err(|e| format!("定位读取偏移失败: {}", e))?;
+
+        let mut buf = vec![0u8; (end - start + 1) as usize];
+        file.read_exact(&mut buf).await
+            .map_err(|e| format!("读取分片失败: {}", e))?;
+
+        Ok(compute_digest(kind, &buf))
+    }
+
+    /// 分段并发写入后没有天然顺序的字节流可以增量哈希，只能对落盘文件做一次
+    /// 顺序重读来计算整文件摘要
+    async fn verify_whole_file_sequential(save_path: &str, kind: DigestKind, expected: &str) -> Result<(), String> {
+        let mut file = tokio::fs::File::open(save_path).await
+            .map_err(|e| format!("打开文件失败: {}", e))?;
+        let mut hasher = IncrementalHasher::new(kind);
+        let mut buf = vec![0u8; 1024 * 1024];
+
+        loop {
+            let n = file.read(&mut buf).await.map_err(|e| format!("读取文件失败: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        let actual = hasher.finalize_hex();
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(format!("Metalink 摘要校验失败: 期望 {}, 实际 {}", expected, actual))
+        }
+    }
+
+    /// 回退路径: 没有镜像支持 range（或文件大小未知）时，整文件流式下载到单个镜像
+    async fn download_whole_file(
+        &self,
+        client: &reqwest::Client,
+        best_url: &str,
+        save_path: &str,
+        expected_digest: Option<(DigestKind, String)>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        eprintln!("没有镜像支持 Range 分段，退化为整文件下载，选择镜像: {}", best_url);
+
+        // 先 HEAD 探测一次，拿到 Accept-Ranges/ETag/Last-Modified，决定能否续传
+        let head = client.head(best_url).send().await.ok();
+        let accept_ranges = head.as_ref()
+            .map(|r| r.headers().get(ACCEPT_RANGES)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.eq_ignore_ascii_case("bytes"))
+                .unwrap_or(false))
+            .unwrap_or(false);
+        let total_size = head.as_ref().and_then(|r| r.content_length()).map(|v| v as i64);
+        let etag = head.as_ref()
+            .and_then(|r| r.headers().get(reqwest::header::ETAG))
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let last_modified = head.as_ref()
+            .and_then(|r| r.headers().get(reqwest::header::LAST_MODIFIED))
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let mut resumable = ResumableFile::open(best_url, save_path, total_size, etag, last_modified).await?;
+
+        let mut request = client.get(best_url);
+        if accept_ranges && resumable.resume_offset() > 0 {
+            request = request.header(RANGE, format!("bytes={}-", resumable.resume_offset()));
+        }
+
+        let mut response = request.send().await
+            .map_err(|e| format!("Metalink HTTP 请求失败: {}", e))?;
+
+        if resumable.resume_offset() > 0 && response.status().as_u16() != 206 {
+            resumable.reset().await?;
+            response = client.get(best_url).send().await
+                .map_err(|e| format!("Metalink HTTP 请求失败: {}", e))?;
+        }
+
+        if let Some(total) = resumable.total_size().or_else(|| Some(response.content_length().unwrap_or(0) as i64)) {
+            if total > 0 {
+                if let Some(ref monitor) = self.monitor {
+                    monitor.set_total_bytes(total);
+                }
+            }
+        }
+
+        // 续传时把已经落盘的前缀重新喂给哈希器，保证最终摘要仍然覆盖整个文件
+        let mut hasher = expected_digest.as_ref().map(|(kind, _)| IncrementalHasher::new(*kind));
+        let resumed_from = resumable.resume_offset();
+        if resumed_from > 0 {
+            if let Some(ref mut hasher) = hasher {
+                hasher.update(&resumable.read_existing_prefix().await?);
+            }
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut downloaded: i64 = resumed_from;
+        // 流式下载天然是按顺序到达的，所以摘要可以随每个 chunk 边写边算，
+        // 不需要像分段下载那样在结束后再整份重读文件
+
+        while let Some(chunk) = stream.next().await {
+            let bytes = chunk.map_err(|e| format!("流读取失败: {}", e))?;
+            resumable.write_all(&bytes).await?;
+            if let Some(ref mut hasher) = hasher {
+                hasher.update(&bytes);
+            }
+            downloaded += bytes.len() as i64;
+            if let Some(ref monitor) = self.monitor {
+                monitor.add_bytes(bytes.len() as i64).await;
+            }
+        }
+        resumable.record_progress();
+
+        if let (Some(hasher), Some((_, expected))) = (hasher, expected_digest) {
+            let actual = hasher.finalize_hex();
+            if actual != expected {
+                let _ = tokio::fs::remove_file(super::resumable_download::part_path(save_path)).await;
+                ResumeState::delete(save_path);
+                return Err(format!("Metalink 摘要校验失败: 期望 {}, 实际 {}", expected, actual).into());
+            }
+            eprintln!("Metalink 摘要校验通过");
+        }
+
+        resumable.finish().await?;
+
+        eprintln!("Metalink 下载完成: {:.2} MB", downloaded as f64 / 1024.0 / 1024.0);
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -61,6 +441,7 @@ impl Downloader for MetalinkDownloader {
         let file_name = &file_entry.name;
 
         eprintln!("Metalink 文件名: {}", file_name);
+        let expected_digest = pick_whole_file_digest(file_entry);
         if let Some(size) = file_entry.size {
             eprintln!("Metalink 文件大小: {} bytes ({:.2} MB)", size, size as f64 / 1024.0 / 1024.0);
             if let Some(ref monitor) = self.monitor {
@@ -87,48 +468,110 @@ impl Downloader for MetalinkDownloader {
             return Err("Metalink 中没有可用的 HTTP(S) 镜像 URL".into());
         }
 
-        eprintln!("找到 {} 个镜像 URL，使用优先级最高的镜像", mirror_urls.len());
+        eprintln!("找到 {} 个镜像 URL", mirror_urls.len());
         for (p, u) in &mirror_urls {
             eprintln!("  [优先级={}] {}", p, u);
         }
 
-        // 5. 构建下载任务，使用第一个（最高优先级）URL
-        //    实际下载委托给 HTTP 下载器
-        let best_url = mirror_urls[0].1.clone();
-        eprintln!("选择镜像: {}", best_url);
+        let candidates: Vec<String> = mirror_urls.iter().map(|(_, u)| u.clone()).collect();
+        let best_url = candidates[0].clone();
 
-        // 直接用 reqwest 流式下载（避免循环依赖 HTTPDownloader）
-        let response = client.get(&best_url)
-            .send().await
-            .map_err(|e| format!("Metalink HTTP 请求失败: {}", e))?;
+        // 5. 只有同时知道文件大小、且至少有一个镜像支持 Range 时才值得分段并发；
+        //    否则退化为原来的单镜像整文件下载
+        let file_size = file_entry.size.map(|s| s as i64);
 
-        let total = response.content_length().unwrap_or(0) as i64;
-        if total > 0 {
-            if let Some(ref monitor) = self.monitor {
-                monitor.set_total_bytes(total);
-            }
+        let ranged_mirrors = match file_size {
+            Some(size) if size > 0 => Self::probe_range_support(&client, &candidates, size).await,
+            _ => Vec::new(),
+        };
+
+        if ranged_mirrors.is_empty() {
+            return self.download_whole_file(&client, &best_url, &save_path, expected_digest).await;
         }
 
-        let mut file = tokio::fs::File::create(&save_path).await
+        let file_size = file_size.unwrap();
+        eprintln!("{} 个镜像支持 Range，使用分段并发下载", ranged_mirrors.len());
+
+        let max_retries = if let Some(ref config) = self.base.config {
+            config.read().await.max_retries
+        } else {
+            super::downloader::DEFAULT_MAX_RETRIES
+        };
+
+        // 只在这里读一次：所有分段 worker 在下面一次性全部 spawn 完，下载过程中
+        // 没有再重新评估并发数的挂钩点——`thread_count_handle()` 返回的
+        // `Arc<AtomicUsize>` 没有谁在下载进行中读取它，`SetThreadCount` 控制指令
+        // 不会影响一个已经在跑的 Metalink 下载，只会通过 `DownloadConfig::thread_count`
+        // 影响下一次下载
+        let thread_count = if let Some(ref config) = self.base.config {
+            config.read().await.thread_count.max(1)
+        } else {
+            4
+        };
+
+        super::disk_guard::check_capacity(&save_path, file_size)?;
+
+        // 预创建输出文件并分配到目标大小，每个分段的 worker 各自打开文件句柄
+        // seek 到自己的偏移写入，互不冲突
+        let file = tokio::fs::File::create(&save_path).await
             .map_err(|e| format!("创建文件失败: {}", e))?;
+        super::disk_guard::preallocate(&file, file_size as u64).await?;
+        drop(file);
 
-        let mut stream = response.bytes_stream();
-        let mut downloaded: i64 = 0;
+        let segments = Self::create_segments(file_size, thread_count);
+        let mirrors = Arc::new(MirrorPool::new(ranged_mirrors, MIRROR_DROP_THRESHOLD));
+        let downloaded = Arc::new(AtomicI64::new(0));
 
-        use futures::StreamExt;
-        use tokio::io::AsyncWriteExt;
+        let mut join_set = tokio::task::JoinSet::new();
+        for (start, end) in segments {
+            let client = client.clone();
+            let save_path = save_path.clone();
+            let mirrors = mirrors.clone();
+            let monitor = self.monitor.clone();
+            let downloaded = downloaded.clone();
 
-        while let Some(chunk) = stream.next().await {
-            let bytes = chunk.map_err(|e| format!("流读取失败: {}", e))?;
-            file.write_all(&bytes).await
-                .map_err(|e| format!("写入失败: {}", e))?;
-            downloaded += bytes.len() as i64;
-            if let Some(ref monitor) = self.monitor {
-                monitor.add_bytes(bytes.len() as i64).await;
+            join_set.spawn(async move {
+                let result = Self::download_segment(client, save_path, start, end, mirrors, monitor, max_retries).await;
+                if result.is_ok() {
+                    downloaded.fetch_add(end - start + 1, Ordering::Relaxed);
+                }
+                result
+            });
+        }
+
+        while let Some(joined) = join_set.join_next().await {
+            match joined {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(e) => return Err(format!("分段任务 join 失败: {:?}", e).into()),
             }
         }
 
-        eprintln!("Metalink 下载完成: {:.2} MB", downloaded as f64 / 1024.0 / 1024.0);
+        // 分段下载是多个 worker 并发乱序写入的，没有现成的"顺序到达"的字节流可以
+        // 增量喂给哈希器；有分片哈希时按分片逐个校验并只重取坏的那一片即可，
+        // 否则只能退回到对整份落盘文件做一次顺序重读
+        if let Some(pieces) = file_entry.pieces.as_ref() {
+            if let Some(kind) = DigestKind::from_type_str(&pieces.hash_type) {
+                if let Err(e) = self.verify_pieces(&save_path, pieces, kind, &mirrors, max_retries).await {
+                    let _ = tokio::fs::remove_file(&save_path).await;
+                    return Err(e.into());
+                }
+                eprintln!("Metalink 分片摘要校验通过");
+            }
+        } else if let Some((kind, expected)) = expected_digest {
+            if let Err(e) = Self::verify_whole_file_sequential(&save_path, kind, &expected).await {
+                let _ = tokio::fs::remove_file(&save_path).await;
+                return Err(e.into());
+            }
+            eprintln!("Metalink 摘要校验通过");
+        }
+
+        eprintln!(
+            "Metalink 分段下载完成: {:.2} MB, 使用 {} 个镜像",
+            downloaded.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0,
+            mirrors.mirror_count(),
+        );
+
         Ok(())
     }
 
@@ -136,8 +579,12 @@ impl Downloader for MetalinkDownloader {
         "Metalink".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
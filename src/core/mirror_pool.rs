@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+/// 单个镜像的失败计分
+struct MirrorState {
+    url: String,
+    /// 同一轮下载中的连续失败次数，成功一次清零
+    consecutive_failures: AtomicI64,
+    dropped: std::sync::atomic::AtomicBool,
+}
+
+/// 多镜像故障转移池
+///
+/// 按轮询顺序把分块请求分发到各镜像；某个镜像连续失败次数超过阈值后被标记为
+/// "dropped"，不再接收新分块，它未完成的分块会被分摊给其余存活的镜像。
+/// 只有当所有未被 drop 的镜像都拒绝过同一个分块，这个分块才被认为永久失败。
+pub struct MirrorPool {
+    mirrors: Vec<MirrorState>,
+    next: AtomicUsize,
+    drop_threshold: i64,
+}
+
+impl MirrorPool {
+    pub fn new(urls: Vec<String>, drop_threshold: i64) -> Self {
+        let mirrors = urls
+            .into_iter()
+            .map(|url| MirrorState {
+                url,
+                consecutive_failures: AtomicI64::new(0),
+                dropped: std::sync::atomic::AtomicBool::new(false),
+            })
+            .collect();
+
+        MirrorPool {
+            mirrors,
+            next: AtomicUsize::new(0),
+            drop_threshold,
+        }
+    }
+
+    pub fn mirror_count(&self) -> usize {
+        self.mirrors.len()
+    }
+
+    fn active_count(&self) -> usize {
+        self.mirrors.iter().filter(|m| !m.dropped.load(Ordering::Relaxed)).count()
+    }
+
+    /// 轮询取下一个未被 drop 的镜像
+    pub fn next_mirror(&self) -> Option<String> {
+        let len = self.mirrors.len();
+        if len == 0 {
+            return None;
+        }
+
+        for _ in 0..len {
+            let idx = self.next.fetch_add(1, Ordering::Relaxed) % len;
+            let mirror = &self.mirrors[idx];
+            if !mirror.dropped.load(Ordering::Relaxed) {
+                return Some(mirror.url.clone());
+            }
+        }
+
+        None
+    }
+
+    /// 记录一次成功请求，清零该镜像的连续失败计数
+    pub fn record_success(&self, url: &str) {
+        if let Some(mirror) = self.mirrors.iter().find(|m| m.url == url) {
+            mirror.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// 记录一次失败请求，超过阈值则把该镜像标记为 dropped，
+    /// 返回 `true` 表示这次失败导致了该镜像被 drop
+    pub fn record_failure(&self, url: &str) -> bool {
+        if let Some(mirror) = self.mirrors.iter().find(|m| m.url == url) {
+            let failures = mirror.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if failures > self.drop_threshold && !mirror.dropped.swap(true, Ordering::Relaxed) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 所有镜像是否都已经被 drop（意味着任务无法继续）
+    pub fn all_dropped(&self) -> bool {
+        self.active_count() == 0
+    }
+}
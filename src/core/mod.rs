@@ -1,8 +1,21 @@
 pub mod downloader;
+pub mod archive_extractor;
+pub mod checksum;
+pub mod persistence;
+pub mod mirror_pool;
+pub mod download_journal;
+pub mod resumable_download;
+pub mod retry;
+pub mod disk_guard;
+pub mod alt_svc_cache;
+pub mod rate_limiter;
+pub mod scheduler;
+pub mod status_snapshot;
 pub mod downloader_interface;
 pub mod http_downloader;
 pub mod ftp_downloader;
 pub mod torrent_downloader;
+pub mod torrent_stream;
 pub mod metalink_downloader;
 pub mod ed2k_downloader;
 pub mod http3_downloader;
@@ -11,8 +24,11 @@ pub mod socket_client;
 pub mod websocket_client;
 pub mod send_message;
 pub mod performance_monitor;
+pub mod connection_pool;
+pub mod glob_match;
 pub mod get_downloader;
 pub mod export;
+pub mod ipc_server;
 
 #[cfg(feature = "android")]
 pub mod android_export;
\ No newline at end of file
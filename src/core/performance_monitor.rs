@@ -15,6 +15,15 @@ pub struct PerformanceMonitor {
     chunk_downloads: Arc<AtomicI64>,
     failed_chunks: Arc<AtomicI64>,
     retried_chunks: Arc<AtomicI64>,
+    /// 预期的文件总大小，调用方在知道之后通过 `set_total_bytes` 设置一次；
+    /// `-1` 表示还未设置，ETA/进度百分比在此之前不可算
+    expected_bytes: Arc<AtomicI64>,
+    /// BT 种群统计，只有 `TorrentDownloader` 会调用 `set_swarm_stats` 填充，
+    /// 其它下载器保持 `-1`（未知/不适用），`get_stats` 据此省略这些字段
+    connected_peers: Arc<AtomicI64>,
+    seeders: Arc<AtomicI64>,
+    leechers: Arc<AtomicI64>,
+    uploaded_bytes: Arc<AtomicI64>,
 }
 
 impl PerformanceMonitor {
@@ -30,9 +39,28 @@ impl PerformanceMonitor {
             chunk_downloads: Arc::new(AtomicI64::new(0)),
             failed_chunks: Arc::new(AtomicI64::new(0)),
             retried_chunks: Arc::new(AtomicI64::new(0)),
+            expected_bytes: Arc::new(AtomicI64::new(-1)),
+            connected_peers: Arc::new(AtomicI64::new(-1)),
+            seeders: Arc::new(AtomicI64::new(-1)),
+            leechers: Arc::new(AtomicI64::new(-1)),
+            uploaded_bytes: Arc::new(AtomicI64::new(-1)),
         }
     }
 
+    /// 设置本次下载的预期总字节数，供 ETA 和进度百分比使用
+    pub fn set_total_bytes(&self, total: i64) {
+        self.expected_bytes.store(total, Ordering::Relaxed);
+    }
+
+    /// BT 下载每次轮询 `handle.stats()` 后调用，刷新种群健康度；
+    /// 非 BT 下载器不调用，对应字段保持 `-1` 不出现在 `get_stats` 里
+    pub fn set_swarm_stats(&self, connected_peers: i64, seeders: i64, leechers: i64, uploaded_bytes: i64) {
+        self.connected_peers.store(connected_peers, Ordering::Relaxed);
+        self.seeders.store(seeders, Ordering::Relaxed);
+        self.leechers.store(leechers, Ordering::Relaxed);
+        self.uploaded_bytes.store(uploaded_bytes, Ordering::Relaxed);
+    }
+
     pub async fn add_bytes(&self, bytes: i64) {
         self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
         self.update_speed().await;
@@ -98,7 +126,8 @@ impl PerformanceMonitor {
     }
 
     pub async fn get_stats(&self) -> HashMap<String, serde_json::Value> {
-        let total_bytes = self.total_bytes.load(Ordering::Relaxed);
+        let total_bytes_downloaded = self.total_bytes.load(Ordering::Relaxed);
+        let expected_bytes = self.expected_bytes.load(Ordering::Relaxed);
         let current_speed = *self.current_speed.read().await;
         let average_speed = *self.average_speed.read().await;
         let peak_speed = *self.peak_speed.read().await;
@@ -108,7 +137,7 @@ impl PerformanceMonitor {
         let elapsed_time = self.start_time.elapsed().as_secs_f64();
 
         let mut stats = HashMap::new();
-        stats.insert("total_bytes".to_string(), serde_json::Value::Number(serde_json::Number::from(total_bytes)));
+        stats.insert("total_bytes_downloaded".to_string(), serde_json::Value::Number(serde_json::Number::from(total_bytes_downloaded)));
         stats.insert("current_speed_bps".to_string(), serde_json::Value::Number(serde_json::Number::from(current_speed as i64)));
         stats.insert("current_speed_mbps".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(current_speed / (1024.0 * 1024.0)).unwrap_or(serde_json::Number::from(0))));
         stats.insert("average_speed_bps".to_string(), serde_json::Value::Number(serde_json::Number::from(average_speed as i64)));
@@ -120,6 +149,28 @@ impl PerformanceMonitor {
         stats.insert("retried_chunks".to_string(), serde_json::Value::Number(serde_json::Number::from(retried_chunks)));
         stats.insert("elapsed_time".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(elapsed_time).unwrap_or(serde_json::Number::from(0))));
 
+        if expected_bytes >= 0 {
+            stats.insert("total_bytes".to_string(), serde_json::Value::Number(serde_json::Number::from(expected_bytes)));
+
+            // 跟 BT 客户端的"下次汇报"倒计时一个道理：剩余字节 / 当前速度；
+            // 速度为 0 时剩余时间趋于无穷，用 null 表示算不出来而不是插入 Infinity
+            let remaining_bytes = (expected_bytes - total_bytes_downloaded).max(0);
+            let seconds_remaining = if current_speed > 0.0 {
+                serde_json::Value::Number(serde_json::Number::from_f64(remaining_bytes as f64 / current_speed).unwrap_or(serde_json::Number::from(0)))
+            } else {
+                serde_json::Value::Null
+            };
+            stats.insert("seconds_remaining".to_string(), seconds_remaining);
+        }
+
+        let connected_peers = self.connected_peers.load(Ordering::Relaxed);
+        if connected_peers >= 0 {
+            stats.insert("connected_peers".to_string(), serde_json::Value::Number(serde_json::Number::from(connected_peers)));
+            stats.insert("seeders".to_string(), serde_json::Value::Number(serde_json::Number::from(self.seeders.load(Ordering::Relaxed))));
+            stats.insert("leechers".to_string(), serde_json::Value::Number(serde_json::Number::from(self.leechers.load(Ordering::Relaxed))));
+            stats.insert("uploaded_bytes".to_string(), serde_json::Value::Number(serde_json::Number::from(self.uploaded_bytes.load(Ordering::Relaxed))));
+        }
+
         stats
     }
 
@@ -127,8 +178,8 @@ impl PerformanceMonitor {
         let stats = self.get_stats().await;
 
         println!("=== 下载性能统计 ===");
-        if let Some(total_bytes) = stats.get("total_bytes").and_then(|v| v.as_i64()) {
-            println!("总下载量: {:.2} MB", total_bytes as f64 / (1024.0 * 1024.0));
+        if let Some(total_bytes_downloaded) = stats.get("total_bytes_downloaded").and_then(|v| v.as_i64()) {
+            println!("总下载量: {:.2} MB", total_bytes_downloaded as f64 / (1024.0 * 1024.0));
         }
         if let Some(current_speed_mbps) = stats.get("current_speed_mbps").and_then(|v| v.as_f64()) {
             println!("当前速度: {:.2} MB/s", current_speed_mbps);
@@ -151,6 +202,22 @@ impl PerformanceMonitor {
         if let Some(elapsed_time) = stats.get("elapsed_time").and_then(|v| v.as_f64()) {
             println!("运行时间: {:.1} 秒", elapsed_time);
         }
+        match stats.get("seconds_remaining") {
+            Some(serde_json::Value::Number(n)) => {
+                if let Some(secs) = n.as_f64() {
+                    println!("预计剩余: {:.0} 秒", secs);
+                }
+            }
+            Some(serde_json::Value::Null) => println!("预计剩余: 未知 (速度为 0)"),
+            _ => {}
+        }
+        if let Some(connected_peers) = stats.get("connected_peers").and_then(|v| v.as_i64()) {
+            let seeders = stats.get("seeders").and_then(|v| v.as_i64()).unwrap_or(0);
+            let leechers = stats.get("leechers").and_then(|v| v.as_i64()).unwrap_or(0);
+            let uploaded = stats.get("uploaded_bytes").and_then(|v| v.as_i64()).unwrap_or(0);
+            println!("连接节点: {} (seeders {} / leechers {}), 已上传 {:.2} MB",
+                connected_peers, seeders, leechers, uploaded as f64 / (1024.0 * 1024.0));
+        }
     }
 }
 
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use super::downloader::{DownloadConfig, DownloadTask};
+use super::archive_extractor::ArchiveFormat;
+use super::torrent_downloader::SeedConfig;
+
+/// `DownloadConfig` 中可以安全跨进程持久化的部分
+///
+/// `callback_func` 是一个进程内函数指针，重启后必然失效，所以不持久化；
+/// 调用方在 `restore_downloads` 之后需要对恢复出来的下载器重新设置回调。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedConfig {
+    pub tasks: Vec<DownloadTask>,
+    pub thread_count: usize,
+    pub chunk_size_mb: usize,
+    pub use_callback_url: bool,
+    pub callback_url: Option<String>,
+    pub use_socket: Option<bool>,
+    pub show_name: String,
+    pub user_agent: String,
+    pub extract: Option<ArchiveFormat>,
+    #[serde(default)]
+    pub max_speed_bps: Option<u64>,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+    #[serde(default = "default_max_concurrent_tasks")]
+    pub max_concurrent_tasks: usize,
+    #[serde(default = "default_max_retry_elapsed_secs")]
+    pub max_retry_elapsed_secs: u64,
+    #[serde(default = "super::downloader::default_ed2k_gateways")]
+    pub ed2k_gateways: Vec<String>,
+    #[serde(default = "super::downloader::default_ws_codec")]
+    pub ws_codec: super::downloader::WsCodec,
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    #[serde(default)]
+    pub seed: Option<SeedConfig>,
+    #[serde(default)]
+    pub torrent_session_dir: Option<String>,
+    #[serde(default = "super::downloader::default_conn_pool_max_size")]
+    pub conn_pool_max_size: usize,
+    #[serde(default = "super::downloader::default_conn_pool_idle_timeout_secs")]
+    pub conn_pool_idle_timeout_secs: u64,
+}
+
+fn default_max_retries() -> usize {
+    super::downloader::DEFAULT_MAX_RETRIES
+}
+
+fn default_max_concurrent_tasks() -> usize {
+    super::downloader::DEFAULT_MAX_CONCURRENT_TASKS
+}
+
+fn default_max_retry_elapsed_secs() -> u64 {
+    super::downloader::DEFAULT_MAX_RETRY_ELAPSED_SECS
+}
+
+impl From<&DownloadConfig> for PersistedConfig {
+    fn from(config: &DownloadConfig) -> Self {
+        PersistedConfig {
+            tasks: config.tasks.clone(),
+            thread_count: config.thread_count,
+            chunk_size_mb: config.chunk_size_mb,
+            use_callback_url: config.use_callback_url,
+            callback_url: config.callback_url.clone(),
+            use_socket: config.use_socket,
+            show_name: config.show_name.clone(),
+            user_agent: config.user_agent.clone(),
+            extract: config.extract,
+            max_speed_bps: config.max_speed_bps,
+            max_retries: config.max_retries,
+            max_concurrent_tasks: config.max_concurrent_tasks,
+            max_retry_elapsed_secs: config.max_retry_elapsed_secs,
+            ed2k_gateways: config.ed2k_gateways.clone(),
+            ws_codec: config.ws_codec,
+            auth_token: config.auth_token.clone(),
+            seed: config.seed,
+            torrent_session_dir: config.torrent_session_dir.clone(),
+            conn_pool_max_size: config.conn_pool_max_size,
+            conn_pool_idle_timeout_secs: config.conn_pool_idle_timeout_secs,
+        }
+    }
+}
+
+impl PersistedConfig {
+    pub fn into_download_config(self) -> DownloadConfig {
+        DownloadConfig {
+            tasks: self.tasks,
+            thread_count: self.thread_count,
+            chunk_size_mb: self.chunk_size_mb,
+            callback_func: None,
+            use_callback_url: self.use_callback_url,
+            callback_url: self.callback_url,
+            use_socket: self.use_socket,
+            show_name: self.show_name,
+            user_agent: self.user_agent,
+            extract: self.extract,
+            // 存储目录不在记录里，需要时由调用方在恢复后设置
+            db_path: None,
+            max_speed_bps: self.max_speed_bps,
+            max_retries: self.max_retries,
+            max_concurrent_tasks: self.max_concurrent_tasks,
+            max_retry_elapsed_secs: self.max_retry_elapsed_secs,
+            ed2k_gateways: self.ed2k_gateways,
+            ws_codec: self.ws_codec,
+            auth_token: self.auth_token,
+            seed: self.seed,
+            torrent_session_dir: self.torrent_session_dir,
+            conn_pool_max_size: self.conn_pool_max_size,
+            conn_pool_idle_timeout_secs: self.conn_pool_idle_timeout_secs,
+        }
+    }
+}
+
+/// 持久化记录: 下载器 id、可序列化配置、以及每个分块是否已完成
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedDownloader {
+    pub id: i32,
+    pub config: PersistedConfig,
+    /// 每个分块的完成状态，索引与分块顺序一一对应
+    pub chunk_bitmap: Vec<bool>,
+}
+
+fn entry_path(db_path: &str, id: i32) -> PathBuf {
+    Path::new(db_path).join(format!("{}.json", id))
+}
+
+/// 把一个下载器的当前状态写入 `db_path` 目录 (每个下载器一个 JSON 文件)
+/// 在 create/start/pause/stop 等状态转换时调用，覆盖旧文件
+pub fn save(db_path: &str, entry: &PersistedDownloader) -> std::io::Result<()> {
+    std::fs::create_dir_all(db_path)?;
+    let json = serde_json::to_vec_pretty(entry)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    std::fs::write(entry_path(db_path, entry.id), json)
+}
+
+/// 已完成或已停止的下载器不再需要恢复，从磁盘上移除对应记录
+pub fn prune(db_path: &str, id: i32) -> std::io::Result<()> {
+    let path = entry_path(db_path, id);
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// 加载 `db_path` 目录下的所有持久化记录，用于进程重启后恢复
+pub fn load_all(db_path: &str) -> Vec<PersistedDownloader> {
+    let dir = match std::fs::read_dir(db_path) {
+        Ok(d) => d,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut entries = Vec::new();
+    for item in dir.flatten() {
+        let path = item.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+
+        match std::fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<PersistedDownloader>(&bytes) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!("解析持久化记录 {:?} 失败: {:?}", path, e),
+            },
+            Err(e) => eprintln!("读取持久化记录 {:?} 失败: {:?}", path, e),
+        }
+    }
+
+    entries
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// 多个 worker 共享的令牌桶限速器
+///
+/// 每个 worker 在写入前调用 `acquire` 扣减对应字节数的令牌；令牌耗尽时按配置的
+/// 速率睡够时间再继续。因为所有 worker 共用同一个 `RateLimiter`，动态分片产生
+/// 再多的并发连接，聚合速度也不会超过 `rate_bps`。
+pub struct RateLimiter {
+    tokens: AtomicI64,
+    last_refill: RwLock<Instant>,
+    rate_bps: i64,
+}
+
+impl RateLimiter {
+    pub fn new(rate_bps: u64) -> Self {
+        let rate_bps = rate_bps.max(1) as i64;
+        RateLimiter {
+            // 初始令牌按一秒的配额发放，允许下载一开始就有一次突发
+            tokens: AtomicI64::new(rate_bps),
+            last_refill: RwLock::new(Instant::now()),
+            rate_bps,
+        }
+    }
+
+    /// 在写入 `n` 字节前调用：扣减对应令牌，如果余额变成负数就按速率睡够时间再补充
+    pub async fn acquire(&self, n: i64) {
+        let remaining = self.tokens.fetch_sub(n, Ordering::Relaxed) - n;
+        if remaining < 0 {
+            let wait_secs = (-remaining) as f64 / self.rate_bps as f64;
+            tokio::time::sleep(Duration::from_secs_f64(wait_secs)).await;
+        }
+        self.refill().await;
+    }
+
+    /// 按距离上次补充的时间补充令牌，上限是一秒的配额 (突发上限)
+    async fn refill(&self) {
+        let mut last = self.last_refill.write().await;
+        let now = Instant::now();
+        let elapsed = now.duration_since(*last).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        let replenished = (elapsed * self.rate_bps as f64) as i64;
+        if replenished > 0 {
+            let burst_ceiling = self.rate_bps;
+            let updated = (self.tokens.fetch_add(replenished, Ordering::Relaxed) + replenished).min(burst_ceiling);
+            self.tokens.store(updated, Ordering::Relaxed);
+            *last = now;
+        }
+    }
+
+    pub fn rate_bps(&self) -> i64 {
+        self.rate_bps
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// 单流顺序下载（ED2K / HTTP3 / Metalink 整文件回退路径）的断点续传状态，
+/// 落盘为 `{save_path}.resume.json`，配合同目录下的 `{save_path}.part` 分块文件使用。
+///
+/// 这是与 `download_journal::DownloadJournal`（HTTPDownloader 多分块并发续传日志）
+/// 完全独立的一套机制：那套面向并发分块 worker，这套只面向单条流顺序下载，
+/// 数据结构和校验方式都更简单，靠 `ETag`/`Last-Modified` 而不是分块偏移表判断续传是否有效。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub url: String,
+    pub total_size: Option<i64>,
+    pub downloaded: i64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// 临时落盘文件：下载过程中的数据都写到这里，只有确认完整才 rename 成最终文件
+pub fn part_path(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part", save_path))
+}
+
+fn state_path(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.resume.json", save_path))
+}
+
+fn state_tmp_path(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.resume.json.tmp", save_path))
+}
+
+impl ResumeState {
+    pub fn load(save_path: &str) -> Option<Self> {
+        let data = std::fs::read(state_path(save_path)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// 远端资源没有变化才认为续传状态仍然有效: URL、总大小、ETag/Last-Modified 都要匹配
+    pub fn matches(&self, url: &str, total_size: Option<i64>, etag: &Option<String>, last_modified: &Option<String>) -> bool {
+        self.url == url
+            && self.total_size == total_size
+            && &self.etag == etag
+            && &self.last_modified == last_modified
+    }
+
+    /// 原子写入: 先写临时文件再 rename，避免进程崩溃在写一半时留下损坏的状态文件
+    pub fn save(&self, save_path: &str) -> std::io::Result<()> {
+        let data = serde_json::to_vec(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let tmp_path = state_tmp_path(save_path);
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, state_path(save_path))?;
+        Ok(())
+    }
+
+    pub fn delete(save_path: &str) {
+        let _ = std::fs::remove_file(state_path(save_path));
+    }
+}
+
+/// 管理 `.part` 文件 + 续传状态的小助手，各下载器通过它来"opt-in"续传支持，
+/// 而不是各自重新实现一遍 `.part` 文件生命周期和续传状态校验。
+///
+/// 用法: `open` 根据探测到的远端信息判断能不能续传，返回时 `resume_offset()`
+/// 非零即代表调用方应该带上 `Range: bytes=<offset>-` 发起请求；服务端如果不
+/// 接受 Range（没有返回 206），调用方应调用 `reset` 丢弃续传重新来过。
+pub struct ResumableFile {
+    save_path: String,
+    file: tokio::fs::File,
+    state: ResumeState,
+}
+
+impl ResumableFile {
+    pub async fn open(
+        url: &str,
+        save_path: &str,
+        total_size: Option<i64>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Result<Self, String> {
+        let existing = ResumeState::load(save_path)
+            .filter(|s| s.matches(url, total_size, &etag, &last_modified));
+        let part_len = tokio::fs::metadata(part_path(save_path)).await.ok().map(|m| m.len() as i64);
+
+        if let (Some(state), Some(len)) = (existing, part_len) {
+            // 预分配过的 `.part` 长度是总大小，已写入的只是它的前缀，
+            // 所以文件不短于记录的已下载字节数就能从该偏移接着写
+            if len >= state.downloaded && state.downloaded > 0 {
+                eprintln!("发现可续传进度: {} 已下载 {} bytes", save_path, state.downloaded);
+                let mut file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .open(part_path(save_path)).await
+                    .map_err(|e| format!("打开续传文件失败: {}", e))?;
+                file.seek(std::io::SeekFrom::Start(state.downloaded as u64)).await
+                    .map_err(|e| format!("定位续传偏移失败: {}", e))?;
+                return Ok(ResumableFile { save_path: save_path.to_string(), file, state });
+            }
+            eprintln!("续传文件大小与记录不符，丢弃续传状态重新下载: {}", save_path);
+        }
+
+        if let Some(size) = total_size {
+            super::disk_guard::check_capacity(save_path, size)?;
+        }
+
+        ResumeState::delete(save_path);
+        let file = tokio::fs::File::create(part_path(save_path)).await
+            .map_err(|e| format!("创建文件失败: {}", e))?;
+        if let Some(size) = total_size.filter(|s| *s > 0) {
+            super::disk_guard::preallocate(&file, size as u64).await?;
+        }
+        let state = ResumeState { url: url.to_string(), total_size, downloaded: 0, etag, last_modified };
+        state.save(save_path).map_err(|e| format!("写入续传状态失败: {}", e))?;
+        Ok(ResumableFile { save_path: save_path.to_string(), file, state })
+    }
+
+    /// 非零表示已经有续传进度，调用方应该带 `Range: bytes=<offset>-` 发起请求
+    pub fn resume_offset(&self) -> i64 {
+        self.state.downloaded
+    }
+
+    pub fn total_size(&self) -> Option<i64> {
+        self.state.total_size
+    }
+
+    /// 服务端拒绝了续传请求（没有返回 206）时调用: 清空 `.part` 文件重新从零开始
+    pub async fn reset(&mut self) -> Result<(), String> {
+        eprintln!("服务器不支持 Range 续传，放弃续传进度重新下载: {}", self.save_path);
+        self.file = tokio::fs::File::create(part_path(&self.save_path)).await
+            .map_err(|e| format!("重建文件失败: {}", e))?;
+        self.state.downloaded = 0;
+        ResumeState::delete(&self.save_path);
+        self.state.save(&self.save_path).map_err(|e| format!("写入续传状态失败: {}", e))?;
+        Ok(())
+    }
+
+    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.file.write_all(bytes).await.map_err(|e| format!("写入失败: {}", e))?;
+        self.state.downloaded += bytes.len() as i64;
+        Ok(())
+    }
+
+    /// 读出 `.part` 开头已续传的 `downloaded` 个字节，供续传时重新喂给 MD4/SHA 等哈希器；
+    /// 预分配的 `.part` 比这更长，多出的部分不返回
+    pub async fn read_existing_prefix(&self) -> Result<Vec<u8>, String> {
+        if self.state.downloaded == 0 {
+            return Ok(Vec::new());
+        }
+        let mut data = tokio::fs::read(part_path(&self.save_path)).await
+            .map_err(|e| format!("读取续传文件前缀失败: {}", e))?;
+        data.truncate(self.state.downloaded as usize);
+        Ok(data)
+    }
+
+    /// 供绕过 `write_all` 的调用方使用：数据已经通过另一个文件句柄（比如并发分段
+    /// 下载里每个分段各自打开的定位写入句柄）直接写到了 `.part` 文件的正确偏移上，
+    /// 这里只是把已下载字节数补记到状态里，不会再往 `self.file` 写任何东西
+    pub fn record_external_progress(&mut self, bytes: i64) {
+        self.state.downloaded += bytes;
+    }
+
+    /// 每写完一批数据调用一次，把最新的已下载字节数落盘，供进程重启后续传
+    pub fn record_progress(&self) {
+        if let Err(e) = self.state.save(&self.save_path) {
+            eprintln!("写入续传状态失败: {:?}", e);
+        }
+    }
+
+    /// 下载完成: 只有总字节数和已知的期望总大小一致才把 `.part` rename 成最终文件，
+    /// 并清理续传状态；总大小未知时信任调用方已经确认流已经读完
+    pub async fn finish(mut self) -> Result<(), String> {
+        self.file.flush().await.map_err(|e| format!("flush 失败: {}", e))?;
+        drop(self.file);
+
+        if let Some(expected) = self.state.total_size {
+            if self.state.downloaded != expected {
+                return Err(format!(
+                    "下载字节数与预期不符: 已下载 {}，预期 {}", self.state.downloaded, expected
+                ));
+            }
+        }
+
+        tokio::fs::rename(part_path(&self.save_path), &self.save_path).await
+            .map_err(|e| format!("重命名文件失败: {}", e))?;
+        ResumeState::delete(&self.save_path);
+        Ok(())
+    }
+}
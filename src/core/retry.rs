@@ -0,0 +1,39 @@
+use std::time::{Duration, Instant};
+
+/// 标记为不可重试的错误 (如 HTTP 4xx)，重试只会原样失败，应立刻放弃，不消耗重试预算
+#[derive(Debug)]
+pub struct NonRetryableError(pub String);
+
+impl std::fmt::Display for NonRetryableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NonRetryableError {}
+
+/// 重试退避的基础时长，第 n 次重试（从 1 开始）实际等待 `BASE_RETRY_DELAY * 2^(n-1)` 再叠加抖动
+pub const BASE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// 重试预算耗尽前允许的最长累计等待时间，超过后即使还没用完 `max_retries` 次数也放弃
+pub const DEFAULT_MAX_RETRY_ELAPSED: Duration = Duration::from_secs(60);
+
+/// 0.0~1.0 之间的抖动系数，避免大量并发重试在同一时刻集体触发
+pub fn jitter_fraction() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    (nanos % 1000) as f64 / 1000.0
+}
+
+/// 第 `attempt` 次重试（从 1 开始）应该等待的退避时长，已经叠加了抖动
+pub fn backoff_delay(attempt: usize) -> Duration {
+    let backoff = BASE_RETRY_DELAY * 2u32.pow(attempt.min(16) as u32 - 1);
+    backoff.mul_f64(1.0 + jitter_fraction() * 0.2)
+}
+
+/// 一轮重试循环还能不能再试一次：次数和累计耗时预算只要有一个用完就不能再试
+pub fn retry_budget_exhausted(attempt: usize, max_retries: usize, started: Instant, max_elapsed: Duration) -> bool {
+    attempt > max_retries || started.elapsed() >= max_elapsed
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use once_cell::sync::Lazy;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinSet;
+
+use super::downloader::{DownloadConfig, DownloadTask};
+use super::downloader_interface::Downloader;
+use super::get_downloader::get_downloader;
+
+/// 所有下载任务共享的全局连接配额
+const GLOBAL_CONNECTION_BUDGET: usize = 256;
+
+/// 全局连接信号量：`HTTPDownloader::download_chunk_dynamic` 在每次真正发起请求前
+/// 获取一个许可，请求结束后（无论成功、失败还是重试之间）随 permit 一起释放。
+/// 这样同时运行的多个 `DownloadTask` 公平地瓜分同一个连接上限，而不是各自独立
+/// 开到 `MAX_CONNECTIONS`，N 个任务叠加后把连接数冲到 N 倍。
+pub fn global_connection_semaphore() -> &'static Arc<Semaphore> {
+    static SEMAPHORE: Lazy<Arc<Semaphore>> = Lazy::new(|| Arc::new(Semaphore::new(GLOBAL_CONNECTION_BUDGET)));
+    &SEMAPHORE
+}
+
+/// 调度中的一个任务句柄：持有正在运行的 `Downloader`，以便调度器在任务还没
+/// 结束时对外暴露快照查询；`running`/`thread_count` 都是在 `downloader` 被
+/// `.write().await.download(..)` 拿走写锁之前，从它身上克隆出来的独立句柄
+/// ——取消和调整并发数都直接操作这两个 `Arc`，不需要再去抢那把锁，见
+/// `Downloader::running_handle`/`Downloader::thread_count_handle` 的文档。
+struct ScheduledTask {
+    downloader: Arc<RwLock<Box<dyn Downloader>>>,
+    running: Arc<AtomicBool>,
+    thread_count: Arc<AtomicUsize>,
+}
+
+/// 调度器汇总快照里的一条记录
+pub struct ScheduledTaskSnapshot {
+    pub save_path: String,
+    pub snapshot: Option<Box<dyn std::any::Any + Send>>,
+}
+
+/// 多任务下载调度器
+///
+/// 接受一批 `DownloadTask`，用一个信号量限制同时运行的任务数，并通过
+/// `global_connection_semaphore` 让所有任务共享同一个连接配额。每个任务各自
+/// 克隆一份 `DownloadConfig`（只替换其中的 `tasks`），复用现有的 `get_downloader`
+/// 工厂按 URL 路由到具体下载器实现。
+pub struct DownloadScheduler {
+    config: Arc<RwLock<DownloadConfig>>,
+    tasks: Vec<DownloadTask>,
+    max_concurrent_tasks: usize,
+    active: Arc<RwLock<HashMap<String, ScheduledTask>>>,
+}
+
+impl DownloadScheduler {
+    pub fn new(config: Arc<RwLock<DownloadConfig>>, tasks: Vec<DownloadTask>, max_concurrent_tasks: usize) -> Self {
+        DownloadScheduler {
+            config,
+            tasks,
+            max_concurrent_tasks: max_concurrent_tasks.max(1),
+            active: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// 并发跑完队列里的所有任务；单个任务失败不影响其它任务，
+    /// 返回值按任务原始顺序携带每个任务的结果。
+    pub async fn run(&self) -> Vec<(DownloadTask, Result<(), String>)> {
+        let task_semaphore = Arc::new(Semaphore::new(self.max_concurrent_tasks));
+        let mut join_set = JoinSet::new();
+
+        for (index, task) in self.tasks.clone().into_iter().enumerate() {
+            let task_semaphore = task_semaphore.clone();
+            let base_config = self.config.clone();
+            let active = self.active.clone();
+
+            join_set.spawn(async move {
+                // 限制同时在跑的任务数；空闲许可会在这里被下一个排队的任务立刻抢到，
+                // 从而实现"公平地把连接许可重新分给活跃任务"
+                let _task_permit = task_semaphore.acquire_owned().await.expect("任务并发信号量已关闭");
+
+                let task_config = {
+                    let cfg = base_config.read().await;
+                    let mut cloned = cfg.clone();
+                    cloned.tasks = vec![task.clone()];
+                    cloned
+                };
+
+                let downloader = get_downloader(Arc::new(RwLock::new(task_config))).await;
+                // 必须在 `download()` 拿到下面的写锁之前克隆这两个句柄，否则
+                // `cancel_task`/`cancel_all`/`set_thread_count_all` 只能通过
+                // `.write()`/`.read()` 重新抢同一把锁，而这把锁要等 download() 整个
+                // 跑完才会释放，会死等
+                let running = downloader.running_handle();
+                let thread_count = downloader.thread_count_handle();
+                let downloader = Arc::new(RwLock::new(downloader));
+
+                active.write().await.insert(task.save_path.clone(), ScheduledTask {
+                    downloader: downloader.clone(),
+                    running,
+                    thread_count,
+                });
+
+                let result = downloader.write().await.download(&task).await.map_err(|e| e.to_string());
+
+                active.write().await.remove(&task.save_path);
+
+                (index, task, result)
+            });
+        }
+
+        let mut results = Vec::with_capacity(self.tasks.len());
+        while let Some(joined) = join_set.join_next().await {
+            match joined {
+                Ok(entry) => results.push(entry),
+                Err(e) => eprintln!("调度任务 join 失败: {:?}", e),
+            }
+        }
+
+        // join_next 按完成先后返回，这里按任务原始下标排回去
+        results.sort_by_key(|(index, _, _)| *index);
+        results.into_iter().map(|(_, task, result)| (task, result)).collect()
+    }
+
+    /// 汇总当前仍在运行的任务的状态快照，供调用方监控整批下载的进度
+    pub async fn aggregate_snapshot(&self) -> Vec<ScheduledTaskSnapshot> {
+        let active = self.active.read().await;
+        let mut out = Vec::with_capacity(active.len());
+        for (save_path, scheduled) in active.iter() {
+            let snapshot = scheduled.downloader.read().await.get_snapshot().await;
+            out.push(ScheduledTaskSnapshot { save_path: save_path.clone(), snapshot });
+        }
+        out
+    }
+
+    /// 暂停/取消指定 `save_path` 对应的任务 —— 直接翻转取消句柄，不经过
+    /// `downloader` 那把锁，下载循环会在下一次 worker 完成的检查点看到
+    /// `running == false` 后自行退出。
+    pub async fn cancel_task(&self, save_path: &str) {
+        let active = self.active.read().await;
+        if let Some(scheduled) = active.get(save_path) {
+            scheduled.running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// 取消所有仍在运行的任务，供 `HSDownloader::pause_download`/`stop_download`
+    /// 批量下载模式下一次性叫停整批任务使用
+    pub async fn cancel_all(&self) {
+        let active = self.active.read().await;
+        for scheduled in active.values() {
+            scheduled.running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// 对所有仍在运行的任务转发并发线程数调整，供 `HSDownloader` 转发
+    /// `SetThreadCount` 控制指令给批量下载模式使用；直接翻转 `thread_count`
+    /// 这个 `Arc<AtomicUsize>`，不经过下载器本身的锁。只有 HTTP 下载器的动态分片
+    /// 重分配循环会真正读取它，其它协议拿到这个调整也不会有实际效果
+    pub async fn set_thread_count_all(&self, count: usize) {
+        let active = self.active.read().await;
+        for scheduled in active.values() {
+            scheduled.thread_count.store(count.max(1), Ordering::Relaxed);
+        }
+    }
+}
@@ -5,6 +5,8 @@ use tokio::sync::RwLock;
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
 use super::performance_monitor::PerformanceMonitor;
+use super::resumable_download::ResumableFile;
+use super::connection_pool::ConnPool;
 
 /// SFTP 下载器
 /// 使用 russh (纯 Rust SSH) + russh-sftp 实现异步 SFTP 文件下载
@@ -13,6 +15,378 @@ pub struct SFTPDownloader {
     monitor: Option<Arc<PerformanceMonitor>>,
 }
 
+/// 池子里存放的一条已认证 SSH 连接，附带在它上面打开的 SFTP 子系统会话；
+/// 两者共享同一条 SSH 通道，必须一起存、一起取，不能只缓存其中一个
+struct PooledSftp {
+    session: russh::client::Handle<SshHandler>,
+    sftp: Arc<russh_sftp::client::SftpSession>,
+}
+
+/// 进程级别的 SFTP 连接池，按 (host, port, username) 分桶；同一目标的重复下载
+/// 可以跳过 SSH 握手 + 认证 + 打开 SFTP 子系统，直接复用上一次留下的会话
+static SFTP_POOL: once_cell::sync::Lazy<ConnPool<(String, u16, String), PooledSftp>> =
+    once_cell::sync::Lazy::new(ConnPool::new);
+
+/// `DownloadTask::sftp_segments` 未设置时的默认并发分段数
+pub const DEFAULT_SFTP_SEGMENTS: usize = 4;
+
+/// `DownloadTask::sftp_segment_buffer_kb` 未设置时每个分段的默认读写缓冲区大小
+pub const DEFAULT_SFTP_SEGMENT_BUFFER_KB: usize = 64;
+
+/// 按固定分段数并发下载整个文件：每个分段各自打开一份远程文件句柄（复用同一条
+/// SFTP 会话，`russh_sftp` 的请求/响应按 id 匹配，天然支持并发在途请求）和一份
+/// 独立的本地文件句柄，各自 seek 到分段起点后顺序读写，互不干扰，读到的字节
+/// 直接喂给 `PerformanceMonitor::add_bytes`，而不是等整段下载完再一次性上报。
+///
+/// 只在全新下载（没有续传进度）且 `file_size` 已知时调用；任何一个分段失败都
+/// 让调用方整体回退到单通道顺序下载，不在这里做分段级别的重试。
+///
+/// `Err` 分支携带 `(从文件开头起连续落盘的字节数, 错误信息)`，而不是单纯的错误信息——调用方
+/// 在取消场景下需要这个数字把已经写盘的分段进度记回续传状态，见 `download` 里
+/// 对这个返回值的处理。
+async fn download_segmented(
+    sftp: &Arc<russh_sftp::client::SftpSession>,
+    remote_path: &str,
+    part_path: &std::path::Path,
+    file_size: i64,
+    segments: usize,
+    buffer_size: usize,
+    monitor: &Option<Arc<PerformanceMonitor>>,
+    running: &Arc<std::sync::atomic::AtomicBool>,
+) -> Result<i64, (i64, String)> {
+    let segment_len = file_size / segments as i64;
+    let mut ranges = Vec::with_capacity(segments);
+    for i in 0..segments {
+        let start = i as i64 * segment_len;
+        let end = if i == segments - 1 { file_size } else { start + segment_len };
+        if end > start {
+            ranges.push((start, end));
+        }
+    }
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for (start, end) in ranges {
+        let sftp = sftp.clone();
+        let remote_path = remote_path.to_string();
+        let part_path = part_path.to_path_buf();
+        let monitor = monitor.clone();
+        let running = running.clone();
+        tasks.spawn(async move {
+            let (downloaded, result) = download_segment(sftp, remote_path, part_path, start, end, buffer_size, monitor, running).await;
+            (start, end, downloaded, result)
+        });
+    }
+
+    // 每个分段无论成功、失败还是被取消，都先把自己实际写盘的字节数报回来，
+    // 这样调用方哪怕因为其中一个分段出错/被取消而整体失败，也能把已写盘的进度
+    // 记回续传状态而不是直接丢弃；续传只能从文件开头起连续写好的前缀接着来，
+    // 所以按分段起点排序后累加到第一个没写完的分段为止
+    let mut finished: Vec<(i64, i64, i64)> = Vec::new();
+    let mut first_err: Option<String> = None;
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((start, end, downloaded, result)) => {
+                finished.push((start, end, downloaded));
+                if let Err(e) = result {
+                    if first_err.is_none() {
+                        first_err = Some(e);
+                    }
+                }
+            }
+            Err(e) => {
+                if first_err.is_none() {
+                    first_err = Some(format!("分段下载任务异常: {}", e));
+                }
+            }
+        }
+    }
+
+    finished.sort_by_key(|(start, _, _)| *start);
+    let mut total: i64 = 0;
+    for (start, end, downloaded) in finished {
+        if start != total {
+            break;
+        }
+        total += downloaded;
+        if downloaded < end - start {
+            break;
+        }
+    }
+
+    match first_err {
+        Some(e) => Err((total, e)),
+        None => Ok(total),
+    }
+}
+
+/// 下载 `[start, end)` 这一个字节区间，写入本地 `.part` 文件的对应偏移
+///
+/// 返回值第一项永远是这个分段实际写盘的字节数，无论第二项是 `Ok` 还是 `Err`
+/// ——取消/出错时调用方仍然需要这个数字，把它计入续传状态里已下载的字节数，
+/// 否则下次重试时 `.part` 文件里这个分段已经写好的数据就白白浪费了
+async fn download_segment(
+    sftp: Arc<russh_sftp::client::SftpSession>,
+    remote_path: String,
+    part_path: std::path::PathBuf,
+    start: i64,
+    end: i64,
+    buffer_size: usize,
+    monitor: Option<Arc<PerformanceMonitor>>,
+    running: Arc<std::sync::atomic::AtomicBool>,
+) -> (i64, Result<(), String>) {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    let mut remote_file = match sftp.open(&remote_path).await {
+        Ok(f) => f,
+        Err(e) => return (0, Err(format!("打开远程文件句柄失败 (分段 {}-{}): {}", start, end, e))),
+    };
+    if let Err(e) = remote_file.seek(std::io::SeekFrom::Start(start as u64)).await {
+        return (0, Err(format!("定位远程分段偏移失败: {}", e)));
+    }
+
+    let mut local_file = match tokio::fs::OpenOptions::new().write(true).open(&part_path).await {
+        Ok(f) => f,
+        Err(e) => return (0, Err(format!("打开本地文件失败: {}", e))),
+    };
+    if let Err(e) = local_file.seek(std::io::SeekFrom::Start(start as u64)).await {
+        return (0, Err(format!("定位本地写入偏移失败: {}", e)));
+    }
+
+    let segment_size = end - start;
+    let mut buf = vec![0u8; buffer_size];
+    let mut downloaded: i64 = 0;
+
+    while downloaded < segment_size {
+        if !running.load(std::sync::atomic::Ordering::Relaxed) {
+            return (downloaded, Err(format!("分段 {}-{} 下载已取消", start, end)));
+        }
+
+        let to_read = (buffer_size as i64).min(segment_size - downloaded) as usize;
+        let n = match remote_file.read(&mut buf[..to_read]).await {
+            Ok(n) => n,
+            Err(e) => return (downloaded, Err(format!("读取远程分段失败: {}", e))),
+        };
+        if n == 0 {
+            break;
+        }
+
+        if let Err(e) = local_file.write_all(&buf[..n]).await {
+            return (downloaded, Err(format!("写入本地分段失败: {}", e)));
+        }
+        downloaded += n as i64;
+
+        if let Some(ref monitor) = monitor {
+            monitor.add_bytes(n as i64).await;
+        }
+    }
+
+    if downloaded != segment_size {
+        return (downloaded, Err(format!("分段 {}-{} 下载不完整: {}/{} bytes", start, end, downloaded, segment_size)));
+    }
+
+    (downloaded, Ok(()))
+}
+
+/// 递归遍历远程目录时，遍历到的一条需要关心的条目（文件或目录）
+struct SftpEntry {
+    name: String,
+    size: i64,
+    is_dir: bool,
+    is_symlink: bool,
+}
+
+/// 递归遍历远程目录，在本地镜像出同样的目录结构，返回需要下载的
+/// (远程路径, 本地路径, 大小) 列表；符号链接默认跳过，`include`/`exclude`
+/// glob 按文件相对根目录的路径过滤
+async fn walk_sftp_directory(
+    sftp: &Arc<russh_sftp::client::SftpSession>,
+    remote_root: &str,
+    local_root: &std::path::Path,
+    include: &Option<String>,
+    exclude: &Option<String>,
+    follow_symlinks: bool,
+) -> Result<Vec<(String, std::path::PathBuf, i64)>, String> {
+    let mut files = Vec::new();
+    let mut stack = vec![(remote_root.to_string(), local_root.to_path_buf(), String::new())];
+
+    while let Some((remote_dir, local_dir, rel_prefix)) = stack.pop() {
+        tokio::fs::create_dir_all(&local_dir)
+            .await
+            .map_err(|e| format!("创建目录失败 {}: {}", local_dir.display(), e))?;
+
+        let raw_entries = sftp.read_dir(&remote_dir)
+            .await
+            .map_err(|e| format!("列出目录失败 {}: {}", remote_dir, e))?;
+
+        let entries: Vec<SftpEntry> = raw_entries
+            .into_iter()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                if name == "." || name == ".." {
+                    return None;
+                }
+                let meta = entry.metadata();
+                Some(SftpEntry {
+                    name,
+                    size: meta.size.unwrap_or(0) as i64,
+                    is_dir: meta.is_dir(),
+                    is_symlink: meta.is_symlink(),
+                })
+            })
+            .collect();
+
+        for entry in entries {
+            if entry.is_symlink && !follow_symlinks {
+                continue;
+            }
+
+            let remote_child = format!("{}/{}", remote_dir.trim_end_matches('/'), entry.name);
+            let local_child = local_dir.join(&entry.name);
+            let rel_child = if rel_prefix.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", rel_prefix, entry.name)
+            };
+
+            if entry.is_dir {
+                stack.push((remote_child, local_child, rel_child));
+            } else if super::glob_match::should_download(&rel_child, include, exclude) {
+                files.push((remote_child, local_child, entry.size));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// 远程路径是目录时的递归下载：先遍历整棵树算出总大小喂给 `PerformanceMonitor`，
+/// 再逐个文件顺序下载；不支持续传，每次都是全新下载整棵树（或 include/exclude 过滤后的子集）
+async fn download_sftp_directory(
+    sftp: &Arc<russh_sftp::client::SftpSession>,
+    remote_root: &str,
+    local_root: &str,
+    task: &DownloadTask,
+    monitor: &Option<Arc<PerformanceMonitor>>,
+    running: &Arc<std::sync::atomic::AtomicBool>,
+) -> Result<i64, String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let local_root_path = std::path::PathBuf::from(local_root);
+    tokio::fs::create_dir_all(&local_root_path)
+        .await
+        .map_err(|e| format!("创建目录失败: {}", e))?;
+
+    let files = walk_sftp_directory(
+        sftp, remote_root, &local_root_path,
+        &task.dir_include_glob, &task.dir_exclude_glob, task.dir_follow_symlinks,
+    ).await?;
+
+    let total_size: i64 = files.iter().map(|(_, _, size)| *size).sum();
+    eprintln!("SFTP 目录下载: {} 个文件，共 {} bytes", files.len(), total_size);
+
+    if let Some(ref monitor) = monitor {
+        monitor.set_total_bytes(total_size);
+    }
+
+    let mut downloaded_total: i64 = 0;
+
+    for (remote_file, local_file, _size) in &files {
+        if !running.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err("SFTP 目录下载已取消".to_string());
+        }
+
+        if let Some(parent) = local_file.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+
+        let mut remote_handle = sftp.open(remote_file)
+            .await
+            .map_err(|e| format!("打开远程文件失败 {}: {}", remote_file, e))?;
+        let mut local_handle = tokio::fs::File::create(local_file)
+            .await
+            .map_err(|e| format!("创建本地文件失败 {}: {}", local_file.display(), e))?;
+
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            if !running.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err("SFTP 目录下载已取消".to_string());
+            }
+
+            let n = remote_handle.read(&mut buf)
+                .await
+                .map_err(|e| format!("读取远程文件失败 {}: {}", remote_file, e))?;
+            if n == 0 {
+                break;
+            }
+
+            local_handle.write_all(&buf[..n])
+                .await
+                .map_err(|e| format!("写入本地文件失败: {}", e))?;
+            downloaded_total += n as i64;
+
+            if let Some(ref monitor) = monitor {
+                monitor.add_bytes(n as i64).await;
+            }
+        }
+    }
+
+    Ok(downloaded_total)
+}
+
+/// 单通道顺序下载路径：打开一个远程文件句柄，有续传偏移就先 seek 过去，然后边读边写；
+/// 分段下载被禁用、续传进度非零、或分段下载失败需要整体回退时都走这里
+async fn sequential_download(
+    sftp: &Arc<russh_sftp::client::SftpSession>,
+    remote_path: &str,
+    resumable: &mut ResumableFile,
+    resume_offset: i64,
+    monitor: &Option<Arc<PerformanceMonitor>>,
+    running: &Arc<std::sync::atomic::AtomicBool>,
+) -> Result<i64, String> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut remote_file = sftp.open(remote_path)
+        .await
+        .map_err(|e| format!("打开远程文件失败: {}", e))?;
+
+    if resume_offset > 0 {
+        remote_file.seek(std::io::SeekFrom::Start(resume_offset as u64))
+            .await
+            .map_err(|e| format!("定位 SFTP 续传偏移失败: {}", e))?;
+    }
+
+    let mut buf = vec![0u8; 64 * 1024]; // 64KB buffer
+    loop {
+        // 每轮读之前检查取消标记，避免大文件下载到一半时没办法中止；`resumable`
+        // 里已经写过的字节数这里先落盘，否则下次重试时 `.part` 文件实际长度和
+        // 记录的 `downloaded` 不一致，整份续传进度会被 `ResumableFile::open` 丢弃
+        if !running.load(std::sync::atomic::Ordering::Relaxed) {
+            resumable.record_progress();
+            return Err("SFTP 下载已取消".to_string());
+        }
+
+        let n = remote_file.read(&mut buf)
+            .await
+            .map_err(|e| format!("读取远程文件失败: {}", e))?;
+        if n == 0 {
+            break;
+        }
+
+        resumable.write_all(&buf[..n])
+            .await
+            .map_err(|e| format!("写入本地文件失败: {}", e))?;
+
+        if let Some(ref monitor) = monitor {
+            monitor.add_bytes(n as i64).await;
+        }
+    }
+    resumable.record_progress();
+
+    Ok(resumable.resume_offset())
+}
+
 impl SFTPDownloader {
     pub async fn new(config: Arc<RwLock<DownloadConfig>>) -> Self {
         let monitor = super::performance_monitor::get_global_monitor().await;
@@ -20,7 +394,7 @@ impl SFTPDownloader {
         SFTPDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
@@ -53,9 +427,53 @@ impl SFTPDownloader {
     }
 }
 
+/// 按 OpenSSH 的方式计算主机密钥指纹，格式 `SHA256:<base64>`，和 `ssh-keygen -lf` 输出一致
+fn fingerprint_of(key: &russh::keys::key::PublicKey) -> String {
+    use sha2::{Digest, Sha256};
+    use base64::Engine;
+
+    let mut hasher = Sha256::new();
+    hasher.update(key.public_key_bytes());
+    format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(hasher.finalize()))
+}
+
+/// 逐行扫描 OpenSSH 风格的 known_hosts 文件（`host[,host...] keytype base64key`），
+/// 找主机名和 base64 公钥都匹配的条目；格式不对的行直接跳过，不因为个别脏行中断校验
+fn known_hosts_contains(path: &str, host: &str, key: &russh::keys::key::PublicKey) -> bool {
+    use russh::keys::PublicKeyBase64;
+
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("读取 known_hosts 失败: {}: {}", path, e);
+            return false;
+        }
+    };
+    let key_b64 = key.public_key_base64();
+
+    content.lines().any(|line| {
+        let mut parts = line.split_whitespace();
+        let hosts = match parts.next() { Some(h) => h, None => return false };
+        if parts.next().is_none() {
+            return false;
+        }
+        match parts.next() {
+            Some(entry_key) => hosts.split(',').any(|h| h == host) && entry_key == key_b64,
+            None => false,
+        }
+    })
+}
+
 /// russh 需要一个 Handler 来处理 SSH 会话事件
-/// 这里使用最简实现：接受所有主机密钥，不做额外处理
-struct SshHandler;
+///
+/// 主机密钥校验优先级: 钉死的指纹 > known_hosts 文件 > `insecure` 放行一切 > 默认拒绝未知主机。
+/// 默认拒绝而不是像早期实现那样无条件接受，是为了堵上中间人攻击的窗口。
+struct SshHandler {
+    host: String,
+    known_hosts_path: Option<String>,
+    pinned_fingerprint: Option<String>,
+    insecure: bool,
+}
 
 #[async_trait::async_trait]
 impl russh::client::Handler for SshHandler {
@@ -63,10 +481,32 @@ impl russh::client::Handler for SshHandler {
 
     async fn check_server_key(
         &mut self,
-        _server_public_key: &russh::keys::key::PublicKey,
+        server_public_key: &russh::keys::key::PublicKey,
     ) -> Result<bool, Self::Error> {
-        // 接受所有主机密钥（下载场景不需要严格验证）
-        Ok(true)
+        if let Some(ref expected) = self.pinned_fingerprint {
+            let actual = fingerprint_of(server_public_key);
+            if &actual == expected {
+                return Ok(true);
+            }
+            eprintln!("SFTP 主机密钥指纹不匹配: 期望 {}, 实际 {}", expected, actual);
+            return Ok(false);
+        }
+
+        if let Some(ref known_hosts_path) = self.known_hosts_path {
+            if known_hosts_contains(known_hosts_path, &self.host, server_public_key) {
+                return Ok(true);
+            }
+            eprintln!("SFTP 主机密钥未出现在 known_hosts 里: {}", self.host);
+            return Ok(false);
+        }
+
+        if self.insecure {
+            eprintln!("警告: 已跳过 SFTP 主机密钥校验 (sftp_insecure=true)，存在中间人风险");
+            return Ok(true);
+        }
+
+        eprintln!("SFTP 拒绝未知主机 {}: 未提供 known_hosts 或钉死指纹，且未设置 sftp_insecure", self.host);
+        Ok(false)
     }
 }
 
@@ -77,101 +517,177 @@ impl Downloader for SFTPDownloader {
         let save_path = task.save_path.clone();
         let monitor = self.monitor.clone();
 
-        eprintln!("SFTP 连接: {}@{}:{} 路径: {}", username, host, port, remote_path);
+        let (pool_max_size, pool_idle_timeout) = if let Some(ref config) = self.base.config {
+            let cfg = config.read().await;
+            (cfg.conn_pool_max_size, std::time::Duration::from_secs(cfg.conn_pool_idle_timeout_secs))
+        } else {
+            (super::connection_pool::DEFAULT_CONN_POOL_MAX_SIZE, std::time::Duration::from_secs(super::connection_pool::DEFAULT_CONN_POOL_IDLE_TIMEOUT_SECS))
+        };
+        let pool_key = (host.clone(), port, username.clone());
 
-        // 1. 配置 SSH 客户端
-        let config = russh::client::Config::default();
-        let config = Arc::new(config);
+        // 复用池子里还活着的连接前先发一次轻量 stat 探活；探活失败（连接已被服务端
+        // 踢掉、网络中断等）就当没有可复用的连接处理，走下面完整的新建连接流程
+        let pooled = match SFTP_POOL.acquire(&pool_key, pool_idle_timeout).await {
+            Some(p) if p.sftp.metadata(".").await.is_ok() => Some(p),
+            _ => None,
+        };
 
-        // 2. 建立 SSH 连接
-        let mut session = russh::client::connect(config, (host.as_str(), port), SshHandler)
-            .await
-            .map_err(|e| format!("SSH 连接失败: {}", e))?;
+        eprintln!("SFTP 连接: {}@{}:{} 路径: {} (复用连接: {})", username, host, port, remote_path, pooled.is_some());
 
-        // 3. 密码认证
-        let auth_result = session.authenticate_password(&username, &password)
-            .await
-            .map_err(|e| format!("SSH 认证失败: {}", e))?;
+        let (session, sftp) = if let Some(PooledSftp { session, sftp }) = pooled {
+            (session, sftp)
+        } else {
+            // 1. 配置 SSH 客户端
+            let config = russh::client::Config::default();
+            let config = Arc::new(config);
+
+            // 2. 建立 SSH 连接
+            let handler = SshHandler {
+                host: host.clone(),
+                known_hosts_path: task.sftp_known_hosts_path.clone(),
+                pinned_fingerprint: task.sftp_pinned_fingerprint.clone(),
+                insecure: task.sftp_insecure,
+            };
+            let mut session = russh::client::connect(config, (host.as_str(), port), handler)
+                .await
+                .map_err(|e| format!("SSH 连接失败: {}", e))?;
+
+            // 3. 认证：设置了私钥就优先公钥认证，没设置才回退密码认证
+            let auth_result = if let Some(ref key_path) = task.sftp_private_key_path {
+                let key_pair = russh::keys::load_secret_key(key_path, task.sftp_private_key_passphrase.as_deref())
+                    .map_err(|e| format!("加载 SFTP 私钥失败: {}", e))?;
+                eprintln!("SFTP 使用公钥认证: {}", key_path);
+                session.authenticate_publickey(&username, Arc::new(key_pair))
+                    .await
+                    .map_err(|e| format!("SSH 公钥认证失败: {}", e))?
+            } else {
+                session.authenticate_password(&username, &password)
+                    .await
+                    .map_err(|e| format!("SSH 认证失败: {}", e))?
+            };
+
+            if !auth_result {
+                return Err("SSH 认证被拒绝".into());
+            }
 
-        if !auth_result {
-            return Err("SSH 密码认证被拒绝".into());
-        }
+            eprintln!("SSH 认证成功");
 
-        eprintln!("SSH 认证成功");
+            // 4. 打开 SFTP 通道
+            let channel = session.channel_open_session()
+                .await
+                .map_err(|e| format!("打开 SSH 通道失败: {}", e))?;
 
-        // 4. 打开 SFTP 通道
-        let channel = session.channel_open_session()
-            .await
-            .map_err(|e| format!("打开 SSH 通道失败: {}", e))?;
+            channel.request_subsystem(true, "sftp")
+                .await
+                .map_err(|e| format!("请求 SFTP 子系统失败: {}", e))?;
 
-        channel.request_subsystem(true, "sftp")
-            .await
-            .map_err(|e| format!("请求 SFTP 子系统失败: {}", e))?;
+            let sftp = russh_sftp::client::SftpSession::new(channel.into_stream())
+                .await
+                .map_err(|e| format!("初始化 SFTP 会话失败: {}", e))?;
 
-        let sftp = russh_sftp::client::SftpSession::new(channel.into_stream())
-            .await
-            .map_err(|e| format!("初始化 SFTP 会话失败: {}", e))?;
+            eprintln!("SFTP 会话已建立");
 
-        eprintln!("SFTP 会话已建立");
+            (session, Arc::new(sftp))
+        };
 
         // 5. 获取远程文件信息
         let metadata = sftp.metadata(&remote_path)
             .await
             .map_err(|e| format!("获取远程文件信息失败: {}", e))?;
 
+        // 远程路径是目录时走递归下载；SFTP 的 stat 对目录和文件都能正常返回属性，
+        // 不需要像 FTP 那样用 SIZE 失败 + CWD 探测去间接猜测
+        if metadata.is_dir() {
+            eprintln!("SFTP 远程路径是目录，进入递归下载: {}", remote_path);
+
+            let running = self.base.running.clone();
+            let start_time = Instant::now();
+            let downloaded = download_sftp_directory(&sftp, &remote_path, &save_path, task, &monitor, &running)
+                .await
+                .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { e.into() })?;
+            let elapsed = start_time.elapsed().as_secs_f64();
+
+            let speed_mbps = if elapsed > 0.0 {
+                (downloaded as f64 / 1024.0 / 1024.0) / elapsed
+            } else { 0.0 };
+            eprintln!("SFTP 目录下载完成: {:.2} MB, 用时 {:.1}s, 速度 {:.2} MB/s",
+                downloaded as f64 / 1024.0 / 1024.0, elapsed, speed_mbps);
+
+            SFTP_POOL.release(pool_key, PooledSftp { session, sftp }, pool_max_size).await;
+            return Ok(());
+        }
+
         let file_size = metadata.size.unwrap_or(0) as i64;
         eprintln!("SFTP 文件大小: {} bytes ({:.2} MB)",
             file_size, file_size as f64 / 1024.0 / 1024.0);
 
-        // 6. 打开远程文件
-        let mut remote_file = sftp.open(&remote_path)
-            .await
-            .map_err(|e| format!("打开远程文件失败: {}", e))?;
-
-        // 7. 创建本地文件并写入
-        let mut local_file = tokio::fs::File::create(&save_path)
-            .await
-            .map_err(|e| format!("创建本地文件失败: {}", e))?;
-
-        let start_time = Instant::now();
-        let mut downloaded: i64 = 0;
+        // 6. 续传判断：复用和 ED2K/HTTP3/Metalink 一样的 `.part` + 续传状态机制，
+        // 用远端 mtime 代替那些协议没有的 ETag；mtime 或大小对不上就视为文件已变化，
+        // `ResumableFile::open` 会丢弃旧的续传状态，从零开始而不是接着拼出损坏的文件
+        let last_modified = metadata.mtime.map(|t| t.to_string());
+        let mut resumable = ResumableFile::open(
+            &task.url,
+            &save_path,
+            Some(file_size).filter(|s| *s > 0),
+            None,
+            last_modified,
+        ).await.map_err(|e| format!("初始化续传状态失败: {}", e))?;
 
-        // 流式拷贝
-        use tokio::io::AsyncReadExt;
-        use tokio::io::AsyncWriteExt;
+        let resume_offset = resumable.resume_offset();
 
-        let mut buf = vec![0u8; 64 * 1024]; // 64KB buffer
-        loop {
-            let n = remote_file.read(&mut buf)
-                .await
-                .map_err(|e| format!("读取远程文件失败: {}", e))?;
-            if n == 0 {
-                break;
-            }
+        let segments = task.sftp_segments.unwrap_or(DEFAULT_SFTP_SEGMENTS);
+        let segment_buffer = task.sftp_segment_buffer_kb.unwrap_or(DEFAULT_SFTP_SEGMENT_BUFFER_KB) * 1024;
 
-            local_file.write_all(&buf[..n])
-                .await
-                .map_err(|e| format!("写入本地文件失败: {}", e))?;
+        // 分段并发只在全新下载（没有续传进度）且文件大小已知、能分出至少两段时才划算；
+        // 有续传进度时沿用下面的单通道顺序路径，避免给"只剩一小段"的场景徒增复杂度
+        let try_segmented = resume_offset == 0 && file_size > 0 && segments > 1;
 
-            downloaded += n as i64;
+        let running = self.base.running.clone();
+        if let Some(ref monitor) = monitor {
+            monitor.set_total_bytes(file_size);
         }
 
-        local_file.flush()
-            .await
-            .map_err(|e| format!("刷新文件缓冲失败: {}", e))?;
+        let start_time = Instant::now();
+
+        let downloaded = if try_segmented {
+            let part = super::resumable_download::part_path(&save_path);
+            match download_segmented(&sftp, &remote_path, &part, file_size, segments, segment_buffer, &monitor, &running).await {
+                Ok(total) => {
+                    resumable.record_external_progress(total);
+                    resumable.record_progress();
+                    total
+                }
+                Err((partial, e)) => {
+                    if !running.load(std::sync::atomic::Ordering::Relaxed) {
+                        // 取消导致的失败：退回顺序下载也会立刻在第一轮检查点再次看到
+                        // running == false 而原样失败，没有意义，不如直接把从文件开头起
+                        // 连续写盘的字节数落回续传状态，再把取消错误透传出去
+                        resumable.record_external_progress(partial);
+                        resumable.record_progress();
+                        return Err(e.into());
+                    }
+                    eprintln!("SFTP 分段下载失败，回退到单通道顺序下载: {}", e);
+                    sequential_download(&sftp, &remote_path, &mut resumable, 0, &monitor, &running).await?
+                }
+            }
+        } else {
+            if resume_offset > 0 {
+                eprintln!("SFTP 发现可续传进度: {} 已下载 {} bytes", save_path, resume_offset);
+                if let Some(ref monitor) = monitor {
+                    monitor.add_bytes(resume_offset).await;
+                }
+            }
+            sequential_download(&sftp, &remote_path, &mut resumable, resume_offset, &monitor, &running).await?
+        };
 
         let elapsed = start_time.elapsed().as_secs_f64();
 
-        // 8. 验证大小
-        if file_size > 0 && downloaded != file_size {
-            return Err(format!("SFTP 下载不完整: {}/{} bytes", downloaded, file_size).into());
-        }
+        // 7. 校验总字节数并把 `.part` 原子地 rename 成最终文件
+        resumable.finish().await.map_err(|e| format!("SFTP 下载不完整: {}", e))?;
 
-        // 9. 更新性能监控
-        if let Some(ref monitor) = monitor {
-            monitor.set_total_bytes(downloaded);
-            monitor.add_bytes(downloaded).await;
-        }
+        // 8. 分段路径和顺序路径都已经在各自的读循环里逐块实时上报过 `add_bytes`
+        // （续传已下载的前缀字节数也在上面单独补报了一次），这里不再整体加一遍，
+        // 否则总量会翻倍
 
         let speed_mbps = if elapsed > 0.0 {
             (downloaded as f64 / 1024.0 / 1024.0) / elapsed
@@ -180,9 +696,9 @@ impl Downloader for SFTPDownloader {
         eprintln!("SFTP 下载完成: {:.2} MB, 用时 {:.1}s, 速度 {:.2} MB/s",
             downloaded as f64 / 1024.0 / 1024.0, elapsed, speed_mbps);
 
-        // 10. 关闭 SSH 会话
-        let _ = session.disconnect(russh::Disconnect::ByApplication, "", "en")
-            .await;
+        // 9. 把连接交还给调用方放回池子，而不是断开，这样同一目标的下一次下载
+        // 能跳过 SSH 握手 + 认证 + 打开 SFTP 子系统
+        SFTP_POOL.release(pool_key, PooledSftp { session, sftp }, pool_max_size).await;
 
         Ok(())
     }
@@ -191,8 +707,12 @@ impl Downloader for SFTPDownloader {
         "SFTP".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
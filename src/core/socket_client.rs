@@ -1,14 +1,22 @@
 use std::collections::HashMap;
-use std::io::Write;
-use std::net::TcpStream;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use super::downloader::{Event, EventType};
 
 const SOCKET_SEND_QUEUE_SIZE: usize = 1024;
 
+/// 重连退避的基础时长，第 n 次重连实际等待 `RECONNECT_BASE_DELAY_MS * 2^n` 再叠加抖动，
+/// 封顶 `RECONNECT_MAX_DELAY_MS`；连接一旦建立成功就重置回基础时长
+const RECONNECT_BASE_DELAY_MS: u64 = 1000;
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+
+const SOCKET_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SOCKET_WRITE_TIMEOUT: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgressMessageS {
     #[serde(rename = "Type")]
@@ -24,21 +32,13 @@ pub struct SocketClient {
     send_queue: tokio::sync::broadcast::Sender<Vec<u8>>,
     done: Arc<tokio::sync::Mutex<bool>>,
     close_once: Arc<tokio::sync::Mutex<bool>>,
+    /// 断线期间最近一条 `EventType::Update` 消息，重连成功后立即补发一次，
+    /// 让重连后的第一条消息反映当前进度而不是一个过时的值
+    last_update: Arc<tokio::sync::Mutex<Option<Vec<u8>>>>,
 }
 
 impl SocketClient {
     pub fn new(address: String) -> Self {
-        if address.is_empty() {
-            return SocketClient {
-                address,
-                connection: Arc::new(Mutex::new(None)),
-                connected: Arc::new(Mutex::new(false)),
-                send_queue: tokio::sync::broadcast::channel(SOCKET_SEND_QUEUE_SIZE).0,
-                done: Arc::new(Mutex::new(false)),
-                close_once: Arc::new(Mutex::new(false)),
-            };
-        }
-
         let client = SocketClient {
             address: address.clone(),
             connection: Arc::new(Mutex::new(None)),
@@ -46,33 +46,84 @@ impl SocketClient {
             send_queue: tokio::sync::broadcast::channel(SOCKET_SEND_QUEUE_SIZE).0,
             done: Arc::new(Mutex::new(false)),
             close_once: Arc::new(Mutex::new(false)),
+            last_update: Arc::new(Mutex::new(None)),
         };
 
-        client.connect();
-        client.start_write_loop();
+        if !address.is_empty() {
+            client.spawn_supervisor();
+            client.start_write_loop();
+        }
 
         client
     }
 
-    fn connect(&self) {
-        if self.address.is_empty() {
-            return;
-        }
+    /// 第 `attempt` 次重连（从 0 开始）应该等待的退避时长: 1s, 2s, 4s ... 封顶 30s，叠加抖动
+    fn reconnect_delay(attempt: u32) -> Duration {
+        let base_ms = RECONNECT_BASE_DELAY_MS.saturating_mul(1u64 << attempt.min(5));
+        let capped_ms = base_ms.min(RECONNECT_MAX_DELAY_MS);
+        let jitter_ms = (capped_ms as f64 * 0.2 * super::retry::jitter_fraction()) as u64;
+        Duration::from_millis(capped_ms + jitter_ms)
+    }
+
+    /// 把连接/重连循环交给一个独立任务去跑，`new` 本身立即返回；
+    /// `SocketClient` 只会在既有的 Tokio 运行时里被构造，直接 `tokio::spawn` 即可
+    fn spawn_supervisor(&self) {
+        let address = self.address.clone();
+        let connection = self.connection.clone();
+        let connected = self.connected.clone();
+        let done = self.done.clone();
+        let last_update = self.last_update.clone();
+
+        tokio::spawn(Self::supervise(address, connection, connected, done, last_update));
+    }
+
+    async fn supervise(
+        address: String,
+        connection: Arc<Mutex<Option<TcpStream>>>,
+        connected: Arc<Mutex<bool>>,
+        done: Arc<Mutex<bool>>,
+        last_update: Arc<Mutex<Option<Vec<u8>>>>,
+    ) {
+        let mut attempt = 0u32;
 
-        match TcpStream::connect_timeout(&self.address.parse().unwrap(), Duration::from_secs(10)) {
-            Ok(conn) => {
-                let mut connection = self.connection.blocking_lock();
-                *connection = Some(conn);
+        loop {
+            if *done.lock().await {
+                return;
+            }
 
-                let mut connected = self.connected.blocking_lock();
-                *connected = true;
+            if *connected.lock().await {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                continue;
             }
-            Err(e) => {
-                eprintln!("Socket连接失败: {:?}", e);
+
+            match tokio::time::timeout(SOCKET_CONNECT_TIMEOUT, TcpStream::connect(&address)).await {
+                Ok(Ok(conn)) => {
+                    eprintln!("Socket连接成功: {}", address);
+                    *connection.lock().await = Some(conn);
+                    *connected.lock().await = true;
+                    attempt = 0;
+
+                    // 重连成功后把断线期间缓冲的最后一条进度消息补发一次
+                    if let Some(payload) = last_update.lock().await.clone() {
+                        if let Err(e) = Self::write_raw(&connection, &connected, payload).await {
+                            eprintln!("补发断线前最后一条进度消息失败: {:?}", e);
+                        }
+                    }
+
+                    continue;
+                }
+                Ok(Err(e)) => eprintln!("Socket连接失败: {:?}", e),
+                Err(_) => eprintln!("Socket连接超时: {}", address),
             }
+
+            let delay = Self::reconnect_delay(attempt);
+            attempt += 1;
+            tokio::time::sleep(delay).await;
         }
     }
 
+    /// 消费发送队列并写入当前连接；写失败只标记断线、不终止循环，
+    /// 等 `supervise` 重新建好连接后继续消费同一个队列
     fn start_write_loop(&self) {
         let send_queue = self.send_queue.clone();
         let connection = self.connection.clone();
@@ -88,17 +139,18 @@ impl SocketClient {
                         let d = done.lock().await;
                         *d
                     } => {
-                        break;
+                        return;
                     }
                     result = receiver.recv() => {
                         match result {
                             Ok(payload) => {
                                 if let Err(e) = Self::write_raw(&connection, &connected, payload).await {
                                     eprintln!("Write failed: {:?}", e);
-                                    break;
+                                    *connection.lock().await = None;
+                                    *connected.lock().await = false;
                                 }
                             }
-                            Err(_) => break,
+                            Err(_) => return,
                         }
                     }
                 }
@@ -111,18 +163,16 @@ impl SocketClient {
         connected: &Arc<Mutex<bool>>,
         payload: Vec<u8>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let conn_guard = connection.lock().await;
-                let mut conn = conn_guard.as_ref().ok_or("socket not connected")?.try_clone()?;
-                drop(conn_guard);
-        
-                let conn_connected = connected.lock().await;
-                if !*conn_connected {
-                    return Err("socket not connected".into());
-                }
-                drop(conn_connected);
-        
-                conn.set_write_timeout(Some(Duration::from_secs(3)))?;
-        conn.write_all(&payload)?;
+        let mut conn_guard = connection.lock().await;
+        let conn = conn_guard.as_mut().ok_or("socket not connected")?;
+
+        let conn_connected = connected.lock().await;
+        if !*conn_connected {
+            return Err("socket not connected".into());
+        }
+        drop(conn_connected);
+
+        tokio::time::timeout(SOCKET_WRITE_TIMEOUT, conn.write_all(&payload)).await??;
 
         Ok(())
     }
@@ -134,12 +184,6 @@ impl SocketClient {
         }
         drop(done);
 
-        let connected = self.connected.lock().await;
-        if !*connected {
-            return;
-        }
-        drop(connected);
-
         let data_bytes = match serde_json::to_string(&data) {
             Ok(bytes) => bytes,
             Err(e) => {
@@ -164,7 +208,12 @@ impl SocketClient {
         let json_data = format!("{}\n", json_data).into_bytes();
 
         if event.event_type == EventType::Update {
-            let _ = self.send_queue.send(json_data);
+            // 无论是否在线都先记住最新一条，断线时重连后由 supervisor 补发；
+            // 在线时照常投进发送队列，和之前的语义保持一致
+            *self.last_update.lock().await = Some(json_data.clone());
+            if *self.connected.lock().await {
+                let _ = self.send_queue.send(json_data);
+            }
             return;
         }
 
@@ -188,14 +237,14 @@ impl SocketClient {
         *done = true;
         drop(done);
 
-        let mut connection = self.connection.blocking_lock();
-        if let Some(conn) = connection.take() {
-            let _ = conn.shutdown(std::net::Shutdown::Both);
-        }
-        drop(connection);
-
-        let mut connected = self.connected.blocking_lock();
-        *connected = false;
+        let connection = self.connection.clone();
+        let connected = self.connected.clone();
+        tokio::spawn(async move {
+            if let Some(mut conn) = connection.lock().await.take() {
+                let _ = conn.shutdown().await;
+            }
+            *connected.lock().await = false;
+        });
     }
 }
 
@@ -208,6 +257,7 @@ impl Clone for SocketClient {
             send_queue: self.send_queue.clone(),
             done: self.done.clone(),
             close_once: self.close_once.clone(),
+            last_update: self.last_update.clone(),
         }
     }
 }
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+/// 一个下载器的高层生命周期阶段，供 `get_download_status` 对外展示
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    Queued,
+    Running,
+    Paused,
+    Error,
+    Done,
+}
+
+/// 最近 N 个进度采样，用来把瞬时速度平滑成移动平均值
+const SPEED_SAMPLE_WINDOW: usize = 8;
+
+/// 供 FFI 拉取式查询使用的轻量状态快照
+/// 下载线程通过 `add_downloaded`/`set_total`/`set_phase` 等方法更新它，
+/// 调用方通过 `get_download_status` 随时拉取，不需要保持回调指针存活。
+pub struct StatusTracker {
+    total_bytes: AtomicI64,
+    downloaded_bytes: AtomicI64,
+    active_threads: AtomicI64,
+    completed_chunks: AtomicI64,
+    phase: RwLock<Phase>,
+    samples: RwLock<VecDeque<(Instant, i64)>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusSnapshot {
+    pub total_bytes: i64,
+    pub downloaded_bytes: i64,
+    pub active_threads: i64,
+    pub completed_chunks: i64,
+    pub phase: Phase,
+    pub current_speed_bps: f64,
+}
+
+impl StatusTracker {
+    pub fn new() -> Self {
+        StatusTracker {
+            total_bytes: AtomicI64::new(0),
+            downloaded_bytes: AtomicI64::new(0),
+            active_threads: AtomicI64::new(0),
+            completed_chunks: AtomicI64::new(0),
+            phase: RwLock::new(Phase::Queued),
+            samples: RwLock::new(VecDeque::with_capacity(SPEED_SAMPLE_WINDOW)),
+        }
+    }
+
+    pub fn set_total(&self, total: i64) {
+        self.total_bytes.store(total, Ordering::Relaxed);
+    }
+
+    pub async fn add_downloaded(&self, bytes: i64) {
+        let downloaded = self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+
+        let mut samples = self.samples.write().await;
+        if samples.len() == SPEED_SAMPLE_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), downloaded));
+    }
+
+    pub fn inc_active_threads(&self) {
+        self.active_threads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec_active_threads(&self) {
+        self.active_threads.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_completed_chunks(&self) {
+        self.completed_chunks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub async fn set_phase(&self, phase: Phase) {
+        let mut p = self.phase.write().await;
+        *p = phase;
+    }
+
+    /// 根据最近的采样窗口算一个移动平均速度，而不是瞬时速度
+    async fn smoothed_speed(&self) -> f64 {
+        let samples = self.samples.read().await;
+        if samples.len() < 2 {
+            return 0.0;
+        }
+
+        let (first_time, first_bytes) = samples.front().copied().unwrap();
+        let (last_time, last_bytes) = samples.back().copied().unwrap();
+
+        let elapsed = last_time.duration_since(first_time).as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        (last_bytes - first_bytes) as f64 / elapsed
+    }
+
+    pub async fn snapshot(&self) -> StatusSnapshot {
+        StatusSnapshot {
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+            active_threads: self.active_threads.load(Ordering::Relaxed),
+            completed_chunks: self.completed_chunks.load(Ordering::Relaxed),
+            phase: *self.phase.read().await,
+            current_speed_bps: self.smoothed_speed().await,
+        }
+    }
+}
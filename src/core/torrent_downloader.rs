@@ -1,13 +1,44 @@
 use std::sync::Arc;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use librqbit::{AddTorrent, AddTorrentOptions, Session};
+use librqbit::{AddTorrent, AddTorrentOptions, Session, SessionOptions, SessionPersistenceConfig};
 
 use super::downloader_interface::{Downloader, BaseDownloader};
 use super::downloader::{DownloadTask, DownloadConfig};
 use super::performance_monitor::PerformanceMonitor;
 
+/// 下载完成后继续做种的配置
+///
+/// 达到 `target_ratio`（上传/下载字节比）或者 `max_seed_secs` 任一条件就停止做种，
+/// `target_ratio <= 0.0` 表示不以比例为准，只受 `max_seed_secs` 约束
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SeedConfig {
+    pub target_ratio: f64,
+    pub max_seed_secs: u64,
+}
+
+impl Default for SeedConfig {
+    /// 常见 BT 客户端的默认策略: 比例到 2.0 或做满 2 小时就停
+    fn default() -> Self {
+        SeedConfig {
+            target_ratio: 2.0,
+            max_seed_secs: 7200,
+        }
+    }
+}
+
+/// `TorrentDownloader::list_files` 返回的种子文件条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TorrentFileEntry {
+    /// 文件在种子里的索引，喂给 `DownloadTask::wanted_file_indices` 选择性下载
+    pub index: usize,
+    pub name: String,
+    pub length: u64,
+}
+
 /// BitTorrent 下载器
 /// 支持 magnet: 链接、.torrent 文件 URL、DHT 网络、PEX (Peer Exchange)
 /// 基于 librqbit — 纯 Rust BitTorrent 客户端库
@@ -16,6 +47,22 @@ pub struct TorrentDownloader {
     monitor: Option<Arc<PerformanceMonitor>>,
 }
 
+/// 按 `session_dir` 创建 librqbit `Session`；设置了会话目录就开启持久化，
+/// 种子元数据和分片位图落在该目录下，创建时 librqbit 自己重新挂载上次留存的种子，
+/// 调用方不需要手动重新 `add_torrent` 就能继续之前的下载进度
+pub(crate) async fn open_session(output_dir: PathBuf, session_dir: Option<&str>) -> Result<Arc<Session>, Box<dyn std::error::Error + Send + Sync>> {
+    let opts = match session_dir {
+        Some(dir) => SessionOptions {
+            persistence: Some(SessionPersistenceConfig::Json { folder: Some(PathBuf::from(dir)) }),
+            ..Default::default()
+        },
+        None => SessionOptions::default(),
+    };
+
+    Session::new_with_opts(output_dir, opts).await
+        .map_err(|e| format!("创建 BT Session 失败: {}", e).into())
+}
+
 impl TorrentDownloader {
     pub async fn new(config: Arc<RwLock<DownloadConfig>>) -> Self {
         let monitor = super::performance_monitor::get_global_monitor().await;
@@ -23,12 +70,114 @@ impl TorrentDownloader {
         TorrentDownloader {
             base: BaseDownloader {
                 config: Some(config),
-                running: true,
+                running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
                 ..Default::default()
             },
             monitor,
         }
     }
+
+    /// 只解析种子/磁力链接的元数据，不落地任何文件，供调用方在下载前挑选要的文件
+    ///
+    /// 用 `AddTorrentOptions { list_only: true, .. }` 添加后立即拿到文件树就返回，
+    /// 不经过下载器的 `running`/取消流程，`Session` 随函数返回而释放。
+    pub async fn list_files(task: &DownloadTask) -> Result<Vec<TorrentFileEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let save_path = PathBuf::from(&task.save_path);
+        let output_dir = save_path.parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .to_path_buf();
+
+        let session = Session::new(output_dir).await
+            .map_err(|e| format!("创建 BT Session 失败: {}", e))?;
+
+        let add_torrent = if task.url.starts_with("magnet:") || task.url.ends_with(".torrent") {
+            AddTorrent::from_url(&task.url)
+        } else {
+            return Err(format!("不支持的 BT URL 格式: {}", task.url).into());
+        };
+
+        let opts = AddTorrentOptions {
+            list_only: true,
+            ..Default::default()
+        };
+
+        let response = session.add_torrent(add_torrent, Some(opts)).await
+            .map_err(|e| format!("解析种子元数据失败: {}", e))?;
+
+        match response {
+            librqbit::AddTorrentResponse::ListOnly(info) => {
+                let files = info.info
+                    .iter_filenames_and_lengths()
+                    .map_err(|e| format!("读取种子文件列表失败: {}", e))?
+                    .enumerate()
+                    .map(|(index, (path, length))| TorrentFileEntry {
+                        index,
+                        name: path.to_string_lossy().to_string(),
+                        length,
+                    })
+                    .collect();
+                Ok(files)
+            }
+            _ => Err("种子未以只列模式返回元数据".into()),
+        }
+    }
+
+    /// 进程启动时调用一次，重新挂载 `session_dir` 里持久化的全部种子，
+    /// 未下载完的任务从上次留存的分片位图继续，而不是重新校验/下载；
+    /// 返回被恢复的种子 info-hash 列表，空列表表示目录下没有可恢复的记录
+    pub async fn resume_all(session_dir: &str, output_dir: PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let session = open_session(output_dir, Some(session_dir)).await?;
+
+        let hashes: Vec<String> = session
+            .with_torrents(|torrents| {
+                torrents.map(|(_, handle)| handle.info_hash().as_string()).collect()
+            });
+
+        eprintln!("从 {} 恢复了 {} 个 BT 任务", session_dir, hashes.len());
+        Ok(hashes)
+    }
+
+    /// 为流式播放打开一个可寻址的文件句柄：只挂载 `file_index` 对应的文件，
+    /// 返回的 `FileStream` 在 seek/read 时会阻塞等待 librqbit 把覆盖到的分片下载校验完，
+    /// 这就是"边下边放"的核心——调用方（见 `torrent_stream`）不需要自己管理分片优先级
+    pub async fn open_file_stream(
+        task: &DownloadTask,
+        file_index: usize,
+        session_dir: Option<&str>,
+    ) -> Result<librqbit::FileStream, Box<dyn std::error::Error + Send + Sync>> {
+        let save_path = PathBuf::from(&task.save_path);
+        let output_dir = save_path.parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .to_path_buf();
+
+        let session = open_session(output_dir, session_dir).await?;
+
+        let add_torrent = if task.url.starts_with("magnet:") || task.url.ends_with(".torrent") {
+            AddTorrent::from_url(&task.url)
+        } else {
+            return Err(format!("不支持的 BT URL 格式: {}", task.url).into());
+        };
+
+        // 只挂载这一个文件，流式播放不需要把整个种子的其他文件也拉起来
+        let opts = AddTorrentOptions {
+            only_files: Some(vec![file_index]),
+            ..Default::default()
+        };
+
+        let response = session.add_torrent(add_torrent, Some(opts)).await
+            .map_err(|e| format!("添加种子失败: {}", e))?;
+
+        let handle = match response {
+            librqbit::AddTorrentResponse::Added(_, handle) => handle,
+            librqbit::AddTorrentResponse::AlreadyManaged(_, handle) => handle,
+            librqbit::AddTorrentResponse::ListOnly(_) => {
+                return Err("种子以只列模式添加，无法流式播放".into());
+            }
+        };
+
+        handle.stream(file_index).await
+            .map_err(|e| format!("打开流式文件句柄失败: {}", e).into())
+    }
 }
 
 #[async_trait::async_trait]
@@ -42,9 +191,13 @@ impl Downloader for TorrentDownloader {
 
         eprintln!("BT 下载: {} -> {:?}", task.url, output_dir);
 
-        // 创建 librqbit Session
-        let session = Session::new(output_dir).await
-            .map_err(|e| format!("创建 BT Session 失败: {}", e))?;
+        // 设置了 torrent_session_dir 时开启会话持久化，种子元数据和分片位图
+        // 落到磁盘，进程被杀掉后可以通过 resume_all 恢复而不是重新下载
+        let session_dir = match &self.base.config {
+            Some(config) => config.read().await.torrent_session_dir.clone(),
+            None => None,
+        };
+        let session = open_session(output_dir, session_dir.as_deref()).await?;
 
         // 构建 AddTorrent 参数
         let add_torrent = if task.url.starts_with("magnet:") {
@@ -56,8 +209,10 @@ impl Downloader for TorrentDownloader {
             return Err(format!("不支持的 BT URL 格式: {}", task.url).into());
         };
 
-        // 添加种子并开始下载
+        // 添加种子并开始下载；指定了 wanted_file_indices 时只下载这些文件，
+        // 进度统计 (handle.stats()) 随之只覆盖被选中的文件，不必额外计算
         let opts = AddTorrentOptions {
+            only_files: task.wanted_file_indices.clone(),
             ..Default::default()
         };
 
@@ -97,6 +252,14 @@ impl Downloader for TorrentDownloader {
                     monitor.add_bytes(new_bytes as i64).await;
                     last_reported_bytes = downloaded;
                 }
+
+                // 种群健康度跟下载进度一样每轮都刷新，不必等到有新字节落地
+                let (connected_peers, seeders, leechers) = stats
+                    .live
+                    .as_ref()
+                    .map(|live| (live.snapshot.peer_stats.live, live.snapshot.peer_stats.seen, live.snapshot.peer_stats.live))
+                    .unwrap_or((0, 0, 0));
+                monitor.set_swarm_stats(connected_peers, seeders, leechers, stats.uploaded_bytes as i64);
             }
 
             // 检查是否完成
@@ -106,12 +269,63 @@ impl Downloader for TorrentDownloader {
             }
 
             // 检查是否被取消
-            if !self.base.running {
+            if !self.base.running.load(std::sync::atomic::Ordering::Relaxed) {
                 eprintln!("BT 下载被取消");
                 return Err("BT 下载被用户取消".into());
             }
         }
 
+        // 配置了 seed 时下载完成后不立即丢弃 Session，继续给种群上传数据，
+        // 直到达到目标分享率或做种时长上限，履行 BT 客户端"回馈"的基本礼仪
+        let seed_config = match &self.base.config {
+            Some(config) => config.read().await.seed,
+            None => None,
+        };
+
+        if let Some(seed_config) = seed_config {
+            let downloaded_bytes = total.max(1);
+            let seed_started = Instant::now();
+
+            eprintln!(
+                "开始做种: 目标比例 {:.2}, 最长 {} 秒",
+                seed_config.target_ratio, seed_config.max_seed_secs
+            );
+
+            loop {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+
+                if !self.base.running.load(std::sync::atomic::Ordering::Relaxed) {
+                    eprintln!("做种被用户取消");
+                    break;
+                }
+
+                let stats = handle.stats();
+                let uploaded = stats.uploaded_bytes;
+                let ratio = uploaded as f64 / downloaded_bytes as f64;
+                let seeded_secs = seed_started.elapsed().as_secs();
+
+                let (seeders, leechers) = stats
+                    .live
+                    .as_ref()
+                    .map(|live| (live.snapshot.peer_stats.seen, live.snapshot.peer_stats.live))
+                    .unwrap_or((0, 0));
+                eprintln!(
+                    "做种中: 比例 {:.2}/{:.2}, 已做种 {}s/{}s, seeders/leechers ~{}/{}",
+                    ratio, seed_config.target_ratio, seeded_secs, seed_config.max_seed_secs, seeders, leechers
+                );
+
+                if seed_config.target_ratio > 0.0 && ratio >= seed_config.target_ratio {
+                    eprintln!("做种达到目标比例，停止做种");
+                    break;
+                }
+
+                if seeded_secs >= seed_config.max_seed_secs {
+                    eprintln!("做种达到最长时长，停止做种");
+                    break;
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -119,8 +333,12 @@ impl Downloader for TorrentDownloader {
         "BitTorrent".to_string()
     }
 
-    async fn cancel(&mut self, _downloader: Box<dyn Downloader>) {
-        self.base.running = false;
+    fn running_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+        self.base.running.clone()
+    }
+
+    fn thread_count_handle(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.base.thread_count.clone()
     }
 
     async fn get_snapshot(&self) -> Option<Box<dyn std::any::Any>> {
@@ -0,0 +1,118 @@
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::downloader::DownloadTask;
+use super::torrent_downloader::TorrentDownloader;
+
+const STREAM_READ_CHUNK: usize = 64 * 1024;
+
+/// 监听 `bind_addr`，把种子里 `file_index` 对应的文件按 HTTP `Range` 请求边下边吐出去，
+/// 让媒体播放器可以在下载没完成前就拖动进度条(seek-while-download)。
+/// 每个连接各自通过 `open_file_stream` 新建一个 `Session` 并打开流式句柄，连接之间不共用会话。
+pub async fn serve(
+    bind_addr: String,
+    task: DownloadTask,
+    file_index: usize,
+    session_dir: Option<String>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(&bind_addr).await?;
+    eprintln!("BT 流式服务监听: {}", bind_addr);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let task = task.clone();
+        let session_dir = session_dir.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, task, file_index, session_dir).await {
+                eprintln!("BT 流式连接 {} 处理出错: {:?}", addr, e);
+            }
+        });
+    }
+}
+
+/// 解析请求行和 `Range` 头，用 `list_files` 拿到目标文件长度，再打开可寻址的流式句柄，
+/// seek 到请求起始偏移后持续读取发送；读取过程中会被动等待对应分片下载校验完成
+async fn handle_connection(
+    stream: TcpStream,
+    task: DownloadTask,
+    file_index: usize,
+    session_dir: Option<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Range:").or_else(|| line.strip_prefix("range:")) {
+            range = parse_range(value.trim());
+        }
+    }
+
+    let entries = TorrentDownloader::list_files(&task).await?;
+    let file = entries
+        .into_iter()
+        .find(|f| f.index == file_index)
+        .ok_or_else(|| format!("种子里没有索引为 {} 的文件", file_index))?;
+
+    let mut file_stream = TorrentDownloader::open_file_stream(&task, file_index, session_dir.as_deref()).await?;
+
+    let total = file.length;
+    let (start, end) = match range {
+        Some((s, Some(e))) => (s, e.min(total.saturating_sub(1))),
+        Some((s, None)) => (s, total.saturating_sub(1)),
+        None => (0, total.saturating_sub(1)),
+    };
+    let content_len = end.saturating_sub(start) + 1;
+
+    file_stream.seek(SeekFrom::Start(start)).await?;
+
+    let status_line = if range.is_some() {
+        format!("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n", start, end, total)
+    } else {
+        "HTTP/1.1 200 OK\r\n".to_string()
+    };
+    let headers = format!(
+        "{}Accept-Ranges: bytes\r\nContent-Length: {}\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n\r\n",
+        status_line, content_len
+    );
+
+    let mut conn = reader.into_inner();
+    conn.write_all(headers.as_bytes()).await?;
+
+    let mut remaining = content_len;
+    let mut buf = vec![0u8; STREAM_READ_CHUNK];
+    while remaining > 0 {
+        let to_read = remaining.min(buf.len() as u64) as usize;
+        let n = file_stream.read(&mut buf[..to_read]).await?;
+        if n == 0 {
+            break;
+        }
+        conn.write_all(&buf[..n]).await?;
+        remaining -= n as u64;
+    }
+
+    Ok(())
+}
+
+/// 解析 `bytes=start-end` 形式的 Range 头，`end` 可省略表示到文件末尾；
+/// 解析失败按没带 Range 处理，返回整个文件
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let spec = value.strip_prefix("bytes=")?;
+    let (start_str, end_str) = spec.split_once('-')?;
+    let start = start_str.trim().parse::<u64>().ok()?;
+    let end = if end_str.trim().is_empty() {
+        None
+    } else {
+        end_str.trim().parse::<u64>().ok()
+    };
+    Some((start, end))
+}
@@ -1,13 +1,35 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-use futures::sink::SinkExt;
+use futures::{sink::SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
 use serde::{Deserialize, Serialize};
-use super::downloader::{Event, EventType};
+use super::downloader::{DownloaderCommand, Event, EventType, WsCodec};
 
 const WS_SEND_QUEUE_SIZE: usize = 1024;
 
+/// 重连退避的基础时长，第 n 次重连实际等待 `RECONNECT_BASE_DELAY_MS * 2^n` 再叠加抖动，
+/// 封顶 `RECONNECT_MAX_DELAY_MS`；连接一旦握手成功就重置回基础时长
+const RECONNECT_BASE_DELAY_MS: u64 = 1000;
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+
+/// 握手后等待服务端 `InitAck` 的最长时间，超时视为鉴权失败并按退避策略重连
+const AUTH_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    /// 所有 `WebSocketClient` 共用的运行时，供连接监督任务和关闭任务 spawn 使用，
+    /// 避免每次 `new`/`close` 都临时开一条线程起一个独立的运行时
+    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+type WsStream = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+type WsWriter = SplitSink<WsStream, Message>;
+type WsReader = SplitStream<WsStream>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgressMessageWs {
     #[serde(rename = "Type")]
@@ -16,28 +38,44 @@ pub struct ProgressMessageWs {
     pub msg: String,
 }
 
+/// 远端通过回调 WebSocket 反向下发的控制指令，以 `Type` 字段做内部标签，
+/// 与 `ProgressMessageWs` 的 `Type` 字段是同一套命名习惯
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "Type")]
+enum InboundControlMessage {
+    Pause,
+    Resume,
+    Stop,
+    SetThreadCount { count: usize },
+}
+
 pub struct WebSocketClient {
     url: String,
-    connection: Arc<tokio::sync::Mutex<Option<tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>>>>,
+    connection: Arc<tokio::sync::Mutex<Option<WsWriter>>>,
     connected: Arc<tokio::sync::Mutex<bool>>,
-    send_queue: tokio::sync::broadcast::Sender<Vec<u8>>,
+    send_queue: tokio::sync::broadcast::Sender<Message>,
     done: Arc<tokio::sync::Mutex<bool>>,
     close_once: Arc<tokio::sync::Mutex<bool>>,
+    /// 断线期间最近一条非 Update 事件（Err/完成/Pause 等），重连成功后补发一次，
+    /// 避免长下载末尾的最终状态刚好撞上网络抖动而丢失；只保留最新一条，不是队列
+    pending_final: Arc<tokio::sync::Mutex<Option<Message>>>,
+    /// 远端下发的 Pause/Resume/Stop/SetThreadCount 指令转发到的目标，
+    /// 和 FFI/IPC 共用同一条 `HSDownloader::command_tx`
+    command_tx: Option<mpsc::UnboundedSender<DownloaderCommand>>,
+    /// 进度消息的编码方式，建连时确定，连接期间不会改变
+    codec: WsCodec,
+    /// 设置后每次握手成功都要先发送 `ConnectionInit` 并等待服务端 ACK，
+    /// 鉴权通过前不会标记为已连接、也不会跑读写循环
+    auth_token: Option<String>,
 }
 
 impl WebSocketClient {
-    pub fn new(url: String) -> Self {
-        if url.is_empty() {
-            return WebSocketClient {
-                url,
-                connection: Arc::new(Mutex::new(None)),
-                connected: Arc::new(Mutex::new(false)),
-                send_queue: tokio::sync::broadcast::channel(WS_SEND_QUEUE_SIZE).0,
-                done: Arc::new(Mutex::new(false)),
-                close_once: Arc::new(Mutex::new(false)),
-            };
-        }
-
+    pub fn new(
+        url: String,
+        command_tx: Option<mpsc::UnboundedSender<DownloaderCommand>>,
+        codec: WsCodec,
+        auth_token: Option<String>,
+    ) -> Self {
         let client = WebSocketClient {
             url: url.clone(),
             connection: Arc::new(Mutex::new(None)),
@@ -45,41 +83,17 @@ impl WebSocketClient {
             send_queue: tokio::sync::broadcast::channel(WS_SEND_QUEUE_SIZE).0,
             done: Arc::new(Mutex::new(false)),
             close_once: Arc::new(Mutex::new(false)),
+            pending_final: Arc::new(Mutex::new(None)),
+            command_tx,
+            codec,
+            auth_token,
         };
 
-        client.connect();
-        client.start_write_loop();
-
-        client
-    }
-
-    fn connect(&self) {
-        if self.url.is_empty() {
-            return;
+        if !url.is_empty() {
+            client.spawn_supervisor();
         }
 
-        let ws_url = Self::normalize_websocket_url(&self.url);
-        if ws_url.is_empty() {
-            return;
-        }
-
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            connect_async(&ws_url).await
-        });
-
-        match result {
-            Ok((ws_stream, _)) => {
-                let mut connection = self.connection.blocking_lock();
-                *connection = Some(ws_stream);
-
-                let mut connected = self.connected.blocking_lock();
-                *connected = true;
-            }
-            Err(e) => {
-                eprintln!("WebSocket连接失败: {:?}", e);
-            }
-        }
+        client
     }
 
     fn normalize_websocket_url(raw: &str) -> String {
@@ -101,43 +115,235 @@ impl WebSocketClient {
         format!("{}websocket", ws_url)
     }
 
-    fn start_write_loop(&self) {
-        let send_queue = self.send_queue.clone();
+    /// 第 `attempt` 次重连（从 0 开始）应该等待的退避时长: 1s, 2s, 4s ... 封顶 30s，叠加抖动
+    fn reconnect_delay(attempt: u32) -> Duration {
+        let base_ms = RECONNECT_BASE_DELAY_MS.saturating_mul(1u64 << attempt.min(5));
+        let capped_ms = base_ms.min(RECONNECT_MAX_DELAY_MS);
+        let jitter_ms = (capped_ms as f64 * 0.2 * super::retry::jitter_fraction()) as u64;
+        Duration::from_millis(capped_ms + jitter_ms)
+    }
+
+    /// 把连接/重连/读写循环交给共享 `RUNTIME` 去跑，`new` 本身立即返回；
+    /// 和调用方是否身处某个 Tokio 运行时无关（FFI/JNI 入口通常没有）
+    fn spawn_supervisor(&self) {
+        let url = self.url.clone();
         let connection = self.connection.clone();
         let connected = self.connected.clone();
         let done = self.done.clone();
+        let send_queue = self.send_queue.clone();
+        let pending_final = self.pending_final.clone();
+        let command_tx = self.command_tx.clone();
+        let auth_token = self.auth_token.clone();
+
+        RUNTIME.spawn(Self::supervise(url, connection, connected, done, send_queue, pending_final, command_tx, auth_token));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn supervise(
+        url: String,
+        connection: Arc<Mutex<Option<WsWriter>>>,
+        connected: Arc<Mutex<bool>>,
+        done: Arc<Mutex<bool>>,
+        send_queue: tokio::sync::broadcast::Sender<Message>,
+        pending_final: Arc<Mutex<Option<Message>>>,
+        command_tx: Option<mpsc::UnboundedSender<DownloaderCommand>>,
+        auth_token: Option<String>,
+    ) {
+        let ws_url = Self::normalize_websocket_url(&url);
+        if ws_url.is_empty() {
+            return;
+        }
 
-        tokio::spawn(async move {
-            let mut receiver = send_queue.subscribe();
+        let mut attempt = 0u32;
+
+        loop {
+            if *done.lock().await {
+                return;
+            }
+
+            match connect_async(&ws_url).await {
+                Ok((ws_stream, _)) => {
+                    eprintln!("WebSocket 连接成功: {}", ws_url);
+                    let (mut writer, mut reader) = ws_stream.split();
+
+                    // 设置了鉴权令牌时，先发 ConnectionInit 并等待服务端 ACK，
+                    // 鉴权没通过之前不标记为已连接，也不会跑读写循环，直接进入重连退避
+                    let authed = match &auth_token {
+                        Some(token) => match Self::send_auth_init(&mut writer, token).await {
+                            Ok(()) => Self::await_auth_ack(&mut reader).await,
+                            Err(e) => {
+                                eprintln!("发送鉴权初始化消息失败: {:?}", e);
+                                false
+                            }
+                        },
+                        None => true,
+                    };
+
+                    if !authed {
+                        eprintln!("WebSocket 鉴权失败，断开并按退避策略重连");
+                    } else {
+                        *connection.lock().await = Some(writer);
+                        *connected.lock().await = true;
+                        attempt = 0;
+
+                        // 重连成功后把断线期间缓冲的最后一条非 Update 事件补发一次
+                        if let Some(payload) = pending_final.lock().await.take() {
+                            if let Err(e) = Self::write_raw(&connection, &connected, payload.clone()).await {
+                                eprintln!("补发断线前最后一条消息失败: {:?}", e);
+                                *pending_final.lock().await = Some(payload);
+                            }
+                        }
 
-            loop {
-                tokio::select! {
-                    _ = async {
-                        let d = done.lock().await;
-                        *d
-                    } => {
-                        break;
+                        // 写循环和读循环并发跑在同一条连接上，任何一个先结束（写失败/读到
+                        // 关闭帧/出错）都代表这条连接已经不可用，另一个随之中止后重连
+                        let mut write_task = tokio::spawn(Self::run_write_loop(send_queue.clone(), connection.clone(), connected.clone(), done.clone()));
+                        let mut read_task = tokio::spawn(Self::run_read_loop(reader, command_tx.clone(), done.clone()));
+
+                        tokio::select! {
+                            _ = &mut write_task => read_task.abort(),
+                            _ = &mut read_task => write_task.abort(),
+                        }
+
+                        if *done.lock().await {
+                            return;
+                        }
+                        *connected.lock().await = false;
+                        eprintln!("WebSocket 连接断开，准备重连");
                     }
-                    result = receiver.recv() => {
-                        match result {
-                            Ok(payload) => {
-                                if let Err(e) = Self::write_raw(&connection, &connected, payload).await {
-                                    eprintln!("Write failed: {:?}", e);
-                                    break;
-                                }
+                }
+                Err(e) => {
+                    eprintln!("WebSocket连接失败: {:?}", e);
+                }
+            }
+
+            let delay = Self::reconnect_delay(attempt);
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// 握手成功后发送的鉴权初始化消息，携带共享令牌；格式固定用 JSON 文本帧，
+    /// 不受 `codec` 影响，因为这时双方还没有进入正常的进度消息收发阶段
+    async fn send_auth_init(
+        writer: &mut WsWriter,
+        token: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        #[derive(Serialize)]
+        struct ConnectionInit<'a> {
+            #[serde(rename = "Type")]
+            msg_type: &'static str,
+            token: &'a str,
+        }
+
+        let payload = serde_json::to_string(&ConnectionInit { msg_type: "ConnectionInit", token })?;
+        writer.send(Message::Text(payload)).await?;
+        Ok(())
+    }
+
+    /// 最多等待 `AUTH_ACK_TIMEOUT` 获取服务端对鉴权初始化消息的 ACK；
+    /// 超时、连接关闭或收到非 ACK 内容都视为鉴权失败
+    async fn await_auth_ack(reader: &mut WsReader) -> bool {
+        let frame = match tokio::time::timeout(AUTH_ACK_TIMEOUT, reader.next()).await {
+            Ok(frame) => frame,
+            Err(_) => {
+                eprintln!("等待鉴权 ACK 超时");
+                return false;
+            }
+        };
+
+        let text = match frame {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Binary(bytes))) => match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => return false,
+            },
+            _ => return false,
+        };
+
+        text.contains("InitAck")
+    }
+
+    /// 消费发送队列并写入当前连接，直到写失败（返回，交给上层重连）或 `done` 被置位
+    async fn run_write_loop(
+        send_queue: tokio::sync::broadcast::Sender<Message>,
+        connection: Arc<Mutex<Option<WsWriter>>>,
+        connected: Arc<Mutex<bool>>,
+        done: Arc<Mutex<bool>>,
+    ) {
+        let mut receiver = send_queue.subscribe();
+
+        loop {
+            tokio::select! {
+                _ = async {
+                    let d = done.lock().await;
+                    *d
+                } => {
+                    return;
+                }
+                result = receiver.recv() => {
+                    match result {
+                        Ok(payload) => {
+                            if let Err(e) = Self::write_raw(&connection, &connected, payload).await {
+                                eprintln!("Write failed: {:?}", e);
+                                return;
                             }
-                            Err(_) => break,
                         }
+                        Err(_) => return,
                     }
                 }
             }
-        });
+        }
+    }
+
+    /// 读取远端下发的控制帧并转发到 `command_tx`，直到连接关闭/出错或 `done` 被置位；
+    /// 这是回调 socket 第一次有了"读"的一侧，之前只会单向推送进度
+    async fn run_read_loop(
+        mut reader: WsReader,
+        command_tx: Option<mpsc::UnboundedSender<DownloaderCommand>>,
+        done: Arc<Mutex<bool>>,
+    ) {
+        loop {
+            if *done.lock().await {
+                return;
+            }
+
+            match reader.next().await {
+                Some(Ok(Message::Text(text))) => Self::dispatch_inbound(&text, &command_tx),
+                Some(Ok(Message::Binary(bytes))) => {
+                    if let Ok(text) = String::from_utf8(bytes) {
+                        Self::dispatch_inbound(&text, &command_tx);
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => return,
+                Some(Ok(_)) => {} // Ping/Pong/原始帧不是控制指令，忽略
+                Some(Err(e)) => {
+                    eprintln!("WebSocket 读取出错: {:?}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// 解析一帧文本为 `InboundControlMessage` 并转发成对应的 `DownloaderCommand`，
+    /// 这样远端面板就能通过既有的回调 socket 驱动暂停/恢复/停止，不需要走平台 FFI
+    fn dispatch_inbound(text: &str, command_tx: &Option<mpsc::UnboundedSender<DownloaderCommand>>) {
+        let Some(tx) = command_tx else { return };
+
+        match serde_json::from_str::<InboundControlMessage>(text) {
+            Ok(InboundControlMessage::Pause) => { let _ = tx.send(DownloaderCommand::Pause); }
+            Ok(InboundControlMessage::Resume) => { let _ = tx.send(DownloaderCommand::Resume); }
+            Ok(InboundControlMessage::Stop) => { let _ = tx.send(DownloaderCommand::Stop); }
+            Ok(InboundControlMessage::SetThreadCount { count }) => {
+                let _ = tx.send(DownloaderCommand::SetThreadCount(count));
+            }
+            Err(e) => eprintln!("解析远端控制指令失败: {} ({})", e, text),
+        }
     }
 
     async fn write_raw(
-        connection: &Arc<Mutex<Option<tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>>>>,
+        connection: &Arc<Mutex<Option<WsWriter>>>,
         connected: &Arc<Mutex<bool>>,
-        payload: Vec<u8>,
+        message: Message,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut conn_guard = connection.lock().await;
         let conn = conn_guard.as_mut().ok_or("websocket not connected")?;
@@ -148,12 +354,32 @@ impl WebSocketClient {
         }
         drop(conn_connected);
 
-        let message = Message::Text(String::from_utf8(payload)?);
         conn.send(message).await?;
 
         Ok(())
     }
 
+    /// 把 `ProgressMessageWs` 编码成一帧: JSON 编码为文本帧，MessagePack 编码为二进制帧，
+    /// 消费端在握手阶段约定好 `codec` 后就知道该按哪种格式解析
+    fn encode(&self, message: &ProgressMessageWs) -> Option<Message> {
+        match self.codec {
+            WsCodec::Json => match serde_json::to_string(message) {
+                Ok(data) => Some(Message::Text(data)),
+                Err(e) => {
+                    eprintln!("序列化消息失败: {:?}", e);
+                    None
+                }
+            },
+            WsCodec::MessagePack => match rmp_serde::to_vec(message) {
+                Ok(data) => Some(Message::Binary(data)),
+                Err(e) => {
+                    eprintln!("MessagePack 编码消息失败: {:?}", e);
+                    None
+                }
+            },
+        }
+    }
+
     pub async fn send_message(&self, event: Event, data: HashMap<String, serde_json::Value>) {
         let done = self.done.lock().await;
         if *done {
@@ -161,12 +387,6 @@ impl WebSocketClient {
         }
         drop(done);
 
-        let connected = self.connected.lock().await;
-        if !*connected {
-            return;
-        }
-        drop(connected);
-
         let data_bytes = match serde_json::to_string(&data) {
             Ok(bytes) => bytes,
             Err(e) => {
@@ -180,22 +400,25 @@ impl WebSocketClient {
             msg: data_bytes,
         };
 
-        let json_data = match serde_json::to_string(&message) {
-            Ok(data) => data,
-            Err(e) => {
-                eprintln!("序列化消息失败: {:?}", e);
-                return;
-            }
+        let Some(encoded) = self.encode(&message) else {
+            return;
         };
 
-        let json_data = json_data.into_bytes();
-
         if event.event_type == EventType::Update {
-            let _ = self.send_queue.send(json_data);
+            if *self.connected.lock().await {
+                let _ = self.send_queue.send(encoded);
+            }
+            // 断线时丢弃进度消息，和之前的 coalesce/drop 语义保持一致
             return;
         }
 
-        match self.send_queue.send(json_data) {
+        // 非 Update 事件（Err/完成等）: 断线时先缓冲最新一条，重连后由 supervisor 补发
+        if !*self.connected.lock().await {
+            *self.pending_final.lock().await = Some(encoded);
+            return;
+        }
+
+        match self.send_queue.send(encoded) {
             Ok(_) => {}
             Err(_) => {
                 eprintln!("WebSocket发送队列阻塞，丢弃非进度消息");
@@ -203,6 +426,9 @@ impl WebSocketClient {
         }
     }
 
+    /// 关闭客户端：立即置位 `done`/`close_once` 让监督循环下次检查时退出，
+    /// 真正的 WebSocket 关闭握手交给共享 `RUNTIME` 异步完成，调用方不必等待，
+    /// 也不会因为自己已经身处某个 Tokio 运行时里而触发 `block_on` 的 panic/死锁
     pub fn close(&self) {
         let mut close_once = self.close_once.blocking_lock();
         if *close_once {
@@ -215,16 +441,14 @@ impl WebSocketClient {
         *done = true;
         drop(done);
 
-        let mut connection = self.connection.blocking_lock();
-        if let Some(mut conn) = connection.take() {
-            let _ = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(conn.close(None))
-            });
-        }
-        drop(connection);
-
-        let mut connected = self.connected.blocking_lock();
-        *connected = false;
+        let connection = self.connection.clone();
+        let connected = self.connected.clone();
+        RUNTIME.spawn(async move {
+            if let Some(mut conn) = connection.lock().await.take() {
+                let _ = conn.close().await;
+            }
+            *connected.lock().await = false;
+        });
     }
 }
 
@@ -237,6 +461,10 @@ impl Clone for WebSocketClient {
             send_queue: self.send_queue.clone(),
             done: self.done.clone(),
             close_once: self.close_once.clone(),
+            pending_final: self.pending_final.clone(),
+            command_tx: self.command_tx.clone(),
+            codec: self.codec,
+            auth_token: self.auth_token.clone(),
         }
     }
-}
\ No newline at end of file
+}